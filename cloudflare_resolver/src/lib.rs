@@ -10,6 +10,8 @@ use std::sync::Arc;
 mod resolver_inner;
 mod response;
 
+/// Clones share the same dns cache.
+#[derive(Clone)]
 pub struct CloudflareResolver {
     resolver: Arc<ResolverInner>,
     dns_type_order: [DnsType; 2],
@@ -28,6 +30,26 @@ impl CloudflareResolver {
             dns_type_order: dns_type_order(),
         }
     }
+
+    /// Number of host names in the dns cache, including the expired ones.
+    pub fn cache_size(&self) -> usize {
+        self.resolver.cache_size()
+    }
+
+    /// Forgets all the resolved host names, they'll be fetched again on the next lookup.
+    pub fn clear_cache(&self) {
+        self.resolver.clear_cache();
+    }
+
+    /// Number of queries sent to the DoH server.
+    pub fn lookups(&self) -> u64 {
+        self.resolver.lookups()
+    }
+
+    /// Number of host names answered from the dns cache.
+    pub fn cache_hits(&self) -> u64 {
+        self.resolver.cache_hits()
+    }
 }
 
 impl Resolve for CloudflareResolver {
@@ -6,11 +6,14 @@ use log::*;
 use reqwest::Client;
 use std::fmt::{Display, Formatter};
 use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 
 pub struct ResolverInner {
     client: Client,
     cache: DashMap<Name, CachedNames>,
+    lookups: AtomicU64,
+    cache_hits: AtomicU64,
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -46,7 +49,12 @@ impl ResolverInner {
             .build()
             .expect("Failed to build http client for cloudflared");
         let cache = DashMap::new();
-        Self { client, cache }
+        Self {
+            client,
+            cache,
+            lookups: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+        }
     }
 
     pub async fn resolve_ips(&self, name: Name, dns_type: DnsType) -> Result<Vec<IpAddr>, String> {
@@ -54,11 +62,13 @@ impl ResolverInner {
             if cached.expires_at >= Instant::now() {
                 let addrs = cached.addrs.clone();
                 debug!("Cache hit: {name} => {addrs:?}");
+                self.cache_hits.fetch_add(1, Ordering::Relaxed);
                 return Ok(addrs);
             }
         }
 
         let start = Instant::now();
+        self.lookups.fetch_add(1, Ordering::Relaxed);
         let doh_url = format!("https://cloudflare-dns.com/dns-query?name={name}&type={dns_type}");
         debug!("DNS fetch url {name} => {doh_url}");
         let request = self
@@ -91,6 +101,22 @@ impl ResolverInner {
 
         Ok(addrs)
     }
+
+    pub fn cache_size(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    pub fn lookups(&self) -> u64 {
+        self.lookups.load(Ordering::Relaxed)
+    }
+
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits.load(Ordering::Relaxed)
+    }
 }
 
 #[cfg(test)]
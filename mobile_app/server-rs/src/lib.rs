@@ -3,7 +3,12 @@ use jni::objects::{JClass, JString};
 use jni::sys::{jint, jstring};
 use jni::JNIEnv;
 use log::{warn, Level};
-use tv_shows_server::start_server;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use tv_shows_server::{start_server, ServerHandle, CONFIG_FILE};
+
+static SERVER: Mutex<Option<ServerHandle>> = Mutex::new(None);
 
 #[no_mangle]
 pub extern "system" fn Java_com_pbs_tvshows_server_TvShowsServer_startServer(
@@ -32,9 +37,39 @@ pub extern "system" fn Java_com_pbs_tvshows_server_TvShowsServer_startServer(
         port, async_thread, io_thread, cache_folder,
     );
 
-    let message = match start_server(&cache_folder, async_thread, io_thread, port) {
+    let mut server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+    let config_file = Path::new(&cache_folder).join(CONFIG_FILE);
+    let config_file = config_file.exists().then_some(config_file.as_path());
+    let message = match tv_shows_server::Config::load(config_file)
+        .and_then(|config| start_server(&cache_folder, async_thread, io_thread, address, config))
+    {
         Err(e) => format!("ERROR: {e:?}"),
-        Ok(_) => "SUCCESS".into(),
+        Ok(handle) => {
+            *server = Some(handle);
+            "SUCCESS".into()
+        }
+    };
+    env.new_string(message)
+        .expect("Failed to create java string")
+        .into_inner()
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_pbs_tvshows_server_TvShowsServer_stopServer(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let handle = SERVER.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let message = match handle {
+        Some(handle) => {
+            warn!("Stopping server running at {}", handle.address());
+            match handle.shutdown() {
+                Err(e) => format!("ERROR: {e:?}"),
+                Ok(_) => "SUCCESS".into(),
+            }
+        }
+        None => "ERROR: Server is not running".into(),
     };
     env.new_string(message)
         .expect("Failed to create java string")
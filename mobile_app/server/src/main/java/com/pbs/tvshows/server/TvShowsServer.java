@@ -19,6 +19,8 @@ public class TvShowsServer {
       int port
   );
 
+  private static native String stopServer();
+
   public static synchronized void startServerInBackground(String cacheFolder, int port) {
     if (hasStarted.compareAndSet(false, true)) {
       new Thread(() -> {
@@ -29,4 +31,15 @@ public class TvShowsServer {
       System.out.println("Server is already running.");
     }
   }
+
+  public static synchronized void stopServerInBackground() {
+    if (hasStarted.compareAndSet(true, false)) {
+      new Thread(() -> {
+        String message = stopServer();
+        System.out.println("Server: " + message);
+      }, "TvShowsServerStop").start();
+    } else {
+      System.out.println("Server is not running.");
+    }
+  }
 }
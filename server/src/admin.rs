@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use axum::extract::{Path, State};
+use axum::response::IntoResponse;
+use axum::routing::{delete, post};
+use axum::{Json, Router};
+use serde_json::json;
+use tracing::*;
+
+use crate::app_state::AppState;
+use crate::error::{show_path, HttpError};
+use crate::tv_channels::refresh_tv_channels;
+use crate::tv_episodes::invalidate_metadata;
+use crate::tv_shows::invalidate_tv_show;
+
+/// Endpoints to refresh or drop the cached data without touching the cache folder by hand.
+pub fn admin_routes() -> Router<AppState> {
+    Router::new()
+        .route("/channels/refresh", post(refresh_channels))
+        .route(
+            "/episodes/:tv_channel/:tv_show",
+            delete(invalidate_episodes),
+        )
+        .route("/metadata/:hash", delete(invalidate_episode_metadata))
+        .route("/dns", delete(flush_dns))
+        .route("/source/reload", post(reload_source))
+}
+
+async fn refresh_channels(State(state): State<AppState>) -> Result<impl IntoResponse, HttpError> {
+    info!("Forcing a refresh of the tv channels");
+    let channels = refresh_tv_channels(&state).await?;
+    Ok(Json(json!({
+        "channels": channels.len(),
+        "tv_shows": channels.values().map(Vec::len).sum::<usize>(),
+    })))
+}
+
+async fn invalidate_episodes(
+    State(state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let (tv_channel, tv_show) = show_path(&params)?;
+    info!("Invalidating the episodes of {tv_channel} > {tv_show}");
+    let removed = invalidate_tv_show(&state, tv_channel, tv_show).await?;
+    Ok(Json(json!({ "removed": removed })))
+}
+
+async fn invalidate_episode_metadata(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    info!("Invalidating the metadata of {hash}");
+    let removed = invalidate_metadata(&state, &hash).await?;
+    Ok(Json(json!({ "removed": removed })))
+}
+
+async fn reload_source(State(state): State<AppState>) -> Result<impl IntoResponse, HttpError> {
+    let loaded = state.source.reload().await?;
+    info!("Reloaded {} with {loaded}", state.source.name());
+    Ok(Json(json!({ "loaded": loaded })))
+}
+
+async fn flush_dns(State(state): State<AppState>) -> impl IntoResponse {
+    let removed = state.dns_resolver.cache_size();
+    info!("Flushing {removed} host names from the dns cache");
+    state.dns_resolver.clear_cache();
+    Json(json!({ "removed": removed }))
+}
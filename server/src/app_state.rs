@@ -0,0 +1,103 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use cloudflare_resolver::CloudflareResolver;
+use reqwest::Client;
+use tokio::fs;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::*;
+
+use crate::cleanup::start_cleanup;
+use crate::config::Config;
+use crate::favorites::{Favorite, FavoritesStateWrapper};
+use crate::fetcher::Fetcher;
+use crate::history::{EpisodeHistory, HistoryStateWrapper};
+use crate::http_util::build_http_client;
+use crate::mirror::Mirror;
+use crate::profiles::{load_profiles, ProfilesStateWrapper};
+use crate::sources::{build_content_source, ContentSource};
+use crate::status::FolderSizeCache;
+use crate::store::{Store, FAVORITES, WATCH_HISTORY};
+use crate::tv_channels::TvChannelStateWrapper;
+use crate::tv_shows::{start_tv_shows_processor, TvShowRequest, TvShowsStateWrapper};
+use crate::worker::Worker;
+
+/// Everything a running server needs, shared with the request handlers via axum's `State`.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub cache_folder: Arc<Path>,
+    pub http_client: Client,
+    pub fetcher: Fetcher,
+    pub dns_resolver: CloudflareResolver,
+    pub source: Arc<dyn ContentSource>,
+    pub mirror: Arc<Mirror>,
+    pub tv_channels: Arc<TvChannelStateWrapper>,
+    pub tv_shows: Arc<TvShowsStateWrapper>,
+    pub history: Arc<HistoryStateWrapper>,
+    pub favorites: Arc<FavoritesStateWrapper>,
+    pub profiles: Arc<ProfilesStateWrapper>,
+    pub tv_show_sender: UnboundedSender<TvShowRequest>,
+    pub worker: Worker,
+    pub folder_size: Arc<FolderSizeCache>,
+}
+
+impl AppState {
+    /// Loads the saved state from the cache folder and spawns the background tasks of a server.
+    pub async fn init(cache_folder: &Path, config: Config) -> anyhow::Result<AppState> {
+        fs::create_dir_all(cache_folder).await?;
+        info!("Using cache folder: {cache_folder:?}");
+
+        let config = Arc::new(config);
+        let cache_folder: Arc<Path> = Arc::from(cache_folder);
+        let dns_resolver = CloudflareResolver::new();
+        let http_client = build_http_client(&config, dns_resolver.clone())?;
+        let mirror = Arc::new(Mirror::load(&cache_folder, &config).await);
+        let fetcher = Fetcher::new(http_client.clone(), &config);
+        let source = build_content_source(fetcher.clone(), &config, mirror.clone()).await;
+        let store = Store::open(&cache_folder).await?;
+        let tv_channels =
+            Arc::new(TvChannelStateWrapper::load(store.clone(), config.expiry()).await);
+        let tv_shows = Arc::new(TvShowsStateWrapper::load(store.clone()).await);
+        let history = Arc::new(
+            HistoryStateWrapper::load(store.clone(), WATCH_HISTORY, EpisodeHistory::key).await,
+        );
+        let favorites =
+            Arc::new(FavoritesStateWrapper::load(store.clone(), FAVORITES, Favorite::key).await);
+        let profiles = Arc::new(load_profiles(store).await);
+        let tv_show_sender =
+            start_tv_shows_processor(tv_shows.clone(), source.clone(), config.clone());
+        let worker = Worker::start();
+        tokio::spawn(start_cleanup(cache_folder.clone(), config.expiry()));
+
+        Ok(AppState {
+            config,
+            cache_folder,
+            http_client,
+            fetcher,
+            dns_resolver,
+            source,
+            mirror,
+            tv_channels,
+            tv_shows,
+            history,
+            favorites,
+            profiles,
+            tv_show_sender,
+            worker,
+            folder_size: Arc::default(),
+        })
+    }
+
+    /// Points the cached urls at the active mirror, if the source site has moved since the last check.
+    pub async fn follow_mirror(&self) -> anyhow::Result<()> {
+        if !self.mirror.take_changed() {
+            return Ok(());
+        }
+        info!("Rebasing the cached urls on {}", self.mirror.active());
+        let rebase = |url: &str| self.mirror.rebase(url);
+        self.tv_channels.rebase_urls(rebase).await?;
+        self.tv_shows.rebase_keys(rebase).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap};
+use axum::response::IntoResponse;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use futures::{stream, StreamExt};
+use tracing::*;
+
+use crate::app_state::AppState;
+use crate::error::{show_path, HttpError, ServerError};
+use crate::models::{EpisodeInfo, TvShow, TvShowEpisodes};
+use crate::tv_shows::{cached_tv_show, tv_show_episodes};
+use crate::utils::{encode_uri_component, slugify, url_id};
+
+/// Most entries of a feed, the readers only care about the recent ones.
+const MAX_ENTRIES: usize = 100;
+
+const ATOM_CONTENT_TYPE: &str = "application/atom+xml; charset=utf-8";
+
+struct Feed {
+    id: String,
+    title: String,
+    author: String,
+    /// Page of the feed in the web app.
+    link: String,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    title: String,
+    /// Web player of the episode, it's the id of the entry too.
+    link: String,
+    /// The air date when the title tells it.
+    updated: DateTime<Utc>,
+}
+
+/// Atom feed of the episodes of a tv show, `/feed/:tv_channel/:tv_show.xml`.
+pub async fn show_feed(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let (tv_channel, tv_show) = show_path(&params)?;
+    let tv_show = tv_show.strip_suffix(".xml").unwrap_or(tv_show);
+    let soap = state
+        .tv_channels
+        .get_tv_show(tv_channel, tv_show)
+        .await
+        .ok_or_else(|| {
+            ServerError::ShowNotFound(format!("Couldn't find Soap with {tv_channel} & {tv_show}"))
+        })?;
+    let (channel_title, _) = state
+        .tv_channels
+        .get_channel(tv_channel)
+        .await
+        .ok_or_else(|| ServerError::ShowNotFound(format!("Couldn't find channel {tv_channel}")))?;
+    // Checks the first page for new episodes, if it hasn't been checked lately.
+    tv_show_episodes(&state, tv_channel, tv_show, false).await?;
+    let episodes = cached_tv_show(&state, &soap).await.unwrap_or_default();
+
+    let base = base_url(&headers);
+    let channel_id = slugify(&channel_title);
+    let show_id = url_id(&soap.url);
+    let mut entries = entries(&base, &channel_title, &soap, &episodes);
+    entries.truncate(MAX_ENTRIES);
+    let feed = Feed {
+        id: format!("{base}/feed/{channel_id}/{show_id}.xml"),
+        title: soap.title.clone(),
+        link: format!(
+            "{base}/tvshow/{}/{}",
+            path_segment(&channel_title),
+            path_segment(&soap.title)
+        ),
+        author: channel_title,
+        entries,
+    };
+    info!("Serving the feed of {tv_channel} > {}", soap.title);
+    Ok(([(header::CONTENT_TYPE, ATOM_CONTENT_TYPE)], feed.to_xml()))
+}
+
+/// Atom feed of the episodes of every show of a channel, `/feed/:tv_channel.xml`.
+///
+/// The first page of the shows which haven't been checked lately is downloaded for it, a few
+/// shows at a time.
+pub async fn channel_feed(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(tv_channel): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let tv_channel = tv_channel.strip_suffix(".xml").unwrap_or(&tv_channel);
+    let (title, tv_shows) = state
+        .tv_channels
+        .get_channel(tv_channel)
+        .await
+        .ok_or_else(|| ServerError::ShowNotFound(format!("Couldn't find channel {tv_channel}")))?;
+
+    let base = base_url(&headers);
+    let channel_id = slugify(&title);
+    let parallelism = state.config.warm_parallelism.max(1);
+    let loaded = stream::iter(tv_shows)
+        .map(|soap| {
+            let (state, base, title) = (&state, &base, &title);
+            async move {
+                let show_id = url_id(&soap.url);
+                if let Err(e) = tv_show_episodes(state, tv_channel, &show_id, false).await {
+                    warn!(
+                        "Failed to load the episodes of {title} > {}: {e:?}",
+                        soap.title
+                    );
+                }
+                let episodes = cached_tv_show(state, &soap).await?;
+                Some(entries(base, title, &soap, &episodes))
+            }
+        })
+        .buffer_unordered(parallelism)
+        .collect::<Vec<_>>()
+        .await;
+    let failed = loaded.iter().filter(|entries| entries.is_none()).count();
+    let mut entries = loaded.into_iter().flatten().flatten().collect::<Vec<_>>();
+    if entries.is_empty() && failed > 0 {
+        return Err(ServerError::Upstream(format!(
+            "Couldn't load the episodes of {failed} shows of {title}"
+        ))
+        .into());
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.updated));
+    entries.truncate(MAX_ENTRIES);
+    let feed = Feed {
+        id: format!("{base}/feed/{channel_id}.xml"),
+        title: title.clone(),
+        link: format!("{base}/channel/{}", path_segment(&title)),
+        author: title,
+        entries,
+    };
+    info!("Serving the feed of {tv_channel}");
+    Ok(([(header::CONTENT_TYPE, ATOM_CONTENT_TYPE)], feed.to_xml()))
+}
+
+/// The entries link to the pages of the web app, which are addressed by the titles.
+fn entries(base: &str, tv_channel: &str, soap: &TvShow, episodes: &TvShowEpisodes) -> Vec<Entry> {
+    let show_path = format!("{}/{}", path_segment(tv_channel), path_segment(&soap.title));
+    let refreshed_at = DateTime::<Utc>::from(episodes.refreshed_at.unwrap_or_else(SystemTime::now));
+    episodes
+        .episodes
+        .iter()
+        .map(|episode| {
+            let info = EpisodeInfo::parse(&soap.title, episode);
+            Entry {
+                title: episode.title.clone(),
+                link: format!("{base}/parts/{show_path}/{}", path_segment(&episode.title)),
+                updated: info
+                    .date
+                    .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok())
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|date| date.and_utc())
+                    .unwrap_or(refreshed_at),
+            }
+        })
+        .collect()
+}
+
+/// The feeds link to the web app on the host which served them.
+fn base_url(headers: &HeaderMap) -> String {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let scheme = header("x-forwarded-proto").unwrap_or("http");
+    let host = header("x-forwarded-host")
+        .or_else(|| header(header::HOST.as_str()))
+        .unwrap_or("localhost");
+    format!("{scheme}://{host}")
+}
+
+/// The spaces of the titles are `%20`, `+` isn't decoded in a path.
+fn path_segment(text: &str) -> String {
+    encode_uri_component(text).replace('+', "%20")
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl Feed {
+    fn to_xml(&self) -> String {
+        let updated = self
+            .entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or_else(Utc::now);
+        let updated = format_time(&updated);
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        xml.push_str(&format!("  <id>{}</id>\n", escape(&self.id)));
+        xml.push_str(&format!("  <title>{}</title>\n", escape(&self.title)));
+        xml.push_str(&format!("  <updated>{updated}</updated>\n"));
+        xml.push_str(&format!(
+            "  <author><name>{}</name></author>\n",
+            escape(&self.author)
+        ));
+        xml.push_str(&format!(
+            "  <link rel=\"self\" href=\"{}\"/>\n",
+            escape(&self.id)
+        ));
+        xml.push_str(&format!(
+            "  <link rel=\"alternate\" href=\"{}\"/>\n",
+            escape(&self.link)
+        ));
+        for entry in &self.entries {
+            xml.push_str("  <entry>\n");
+            xml.push_str(&format!("    <id>{}</id>\n", escape(&entry.link)));
+            xml.push_str(&format!("    <title>{}</title>\n", escape(&entry.title)));
+            xml.push_str(&format!(
+                "    <updated>{}</updated>\n",
+                format_time(&entry.updated)
+            ));
+            xml.push_str(&format!(
+                "    <link rel=\"alternate\" href=\"{}\"/>\n",
+                escape(&entry.link)
+            ));
+            xml.push_str("  </entry>\n");
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use crate::models::{TvEpisode, TvShow, TvShowEpisodes};
+
+    use super::{entries, format_time, Feed};
+
+    #[test]
+    fn test_feed_xml() {
+        let soap = TvShow {
+            title: "Bigg Boss 17".into(),
+            url: "https://example.com/bigg-boss-17/".into(),
+            icon: "icon".into(),
+        };
+        let episode = |title: &str| TvEpisode {
+            id: title.to_lowercase().replace(' ', "-"),
+            title: title.into(),
+            parts: Vec::new(),
+        };
+        let episodes = TvShowEpisodes {
+            episodes: vec![
+                episode("Bigg Boss 17 12th October 2023"),
+                episode("Bigg Boss 17 Weekend Ka Vaar <Special> & More"),
+            ],
+            refreshed_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            ..TvShowEpisodes::default()
+        };
+        let entries = entries("http://tv.local", "Colors", &soap, &episodes);
+        assert_eq!(format_time(&entries[0].updated), "2023-10-12T00:00:00Z");
+        assert_eq!(
+            entries[0].link,
+            "http://tv.local/parts/Colors/Bigg%20Boss%2017/Bigg%20Boss%2017%2012th%20October%202023"
+        );
+
+        let feed = Feed {
+            id: "http://tv.local/feed/colors/bigg-boss-17.xml".into(),
+            title: soap.title.clone(),
+            author: "Colors".into(),
+            link: "http://tv.local/tvshow/Colors/Bigg%20Boss%2017".into(),
+            entries,
+        };
+        let xml = feed.to_xml();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>"));
+        assert!(
+            xml.contains("<title>Bigg Boss 17 Weekend Ka Vaar &lt;Special&gt; &amp; More</title>")
+        );
+        assert!(xml.contains("  <updated>2023-11-14T22:13:20Z</updated>"));
+        assert_eq!(xml.matches("<entry>").count(), 2);
+    }
+}
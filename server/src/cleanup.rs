@@ -1,4 +1,5 @@
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 use futures::future::BoxFuture;
@@ -6,33 +7,43 @@ use futures::FutureExt;
 use tokio::{fs, time};
 use tracing::*;
 
-use crate::utils::{cache_folder, expiry_time, EXPIRY};
+use crate::config::CONFIG_FILE;
+use crate::utils::{expiry_time, MIRROR_FILE, STATE_DB};
 
-pub async fn start_cleanup() -> ! {
-    async fn cleanup() -> anyhow::Result<()> {
+/// Files which are kept whatever their age.
+const KEPT_FILES: [&str; 3] = [STATE_DB, MIRROR_FILE, CONFIG_FILE];
+
+pub async fn start_cleanup(cache_folder: Arc<Path>, expiry: Duration) -> ! {
+    async fn cleanup(cache_folder: &Path, expiry: Duration) -> anyhow::Result<()> {
         info!("Running cleanup task...");
-        let cache_folder = PathBuf::from(cache_folder());
-        let deleted_count = dfs(cache_folder.clone(), &cache_folder).await?;
+        let deleted_count = dfs(cache_folder.to_path_buf(), cache_folder, expiry).await?;
         if deleted_count > 0 {
             info!("Cleaned {deleted_count} expired files/folders");
         }
         Ok(())
     }
 
-    fn dfs(path: PathBuf, cache_folder: &Path) -> BoxFuture<'_, anyhow::Result<u32>> {
-        async {
+    fn dfs(
+        path: PathBuf,
+        cache_folder: &Path,
+        expiry: Duration,
+    ) -> BoxFuture<'_, anyhow::Result<u32>> {
+        async move {
             let mut count = 0;
             let metadata = fs::metadata(&path).await?;
             if metadata.is_dir() {
                 let mut read_dir = fs::read_dir(&path).await?;
                 while let Some(child) = read_dir.next_entry().await? {
-                    count += dfs(child.path(), cache_folder).await?;
+                    count += dfs(child.path(), cache_folder, expiry).await?;
                 }
                 let mut read_dir = fs::read_dir(&path).await?;
                 if read_dir.next_entry().await?.is_none() {
                     count += delete(path, cache_folder).await?;
                 }
-            } else if metadata.is_file() && metadata.modified()?.elapsed()? > EXPIRY {
+            } else if KEPT_FILES.iter().any(|file| path.ends_with(file)) {
+                // The database is written in place, the mirror only when the site moves & the
+                // config by hand, their age says nothing about their content.
+            } else if metadata.is_file() && metadata.modified()?.elapsed()? > expiry {
                 count += delete(path, cache_folder).await?;
             }
             Ok(count)
@@ -56,7 +67,7 @@ pub async fn start_cleanup() -> ! {
     }
 
     loop {
-        cleanup()
+        cleanup(&cache_folder, expiry)
             .await
             .map_err(|e| warn!("Cleanup task failed: {e}"))
             .ok();
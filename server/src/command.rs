@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use tracing::*;
+
+use crate::app_state::AppState;
+use crate::config::Config;
+use crate::profiles::Quality;
+use crate::tv_channels::tv_channels;
+use crate::tv_episodes::resolve_episode;
+use crate::tv_shows::tv_show_episodes;
+
+/// One-off commands which run the scraping pipeline without starting the http server.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Channels,
+    Episodes {
+        tv_channel: String,
+        tv_show: String,
+        load_more: bool,
+    },
+    Resolve {
+        tv_channel: String,
+        tv_show: String,
+        episode: String,
+    },
+}
+
+/// Runs the command against the state saved in the cache folder and returns its result as pretty json.
+///
+/// The state database can only be opened by one process, so this fails while a server is running
+/// on the same cache folder.
+pub fn run_command(cache_dir: &str, config: Config, command: Command) -> anyhow::Result<String> {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    rt.block_on(async {
+        let state = AppState::init(Path::new(cache_dir), config).await?;
+        info!("Running {command:?}");
+        // Later steps look things up in the state, so every command loads whatever precedes it.
+        let channels = tv_channels(&state).await?;
+        let json = match command {
+            Command::Channels => serde_json::to_string_pretty(&channels)?,
+            Command::Episodes {
+                tv_channel,
+                tv_show,
+                load_more,
+            } => {
+                let episodes = tv_show_episodes(&state, &tv_channel, &tv_show, load_more).await?;
+                serde_json::to_string_pretty(&episodes)?
+            }
+            Command::Resolve {
+                tv_channel,
+                tv_show,
+                episode,
+            } => {
+                tv_show_episodes(&state, &tv_channel, &tv_show, false).await?;
+                let parts =
+                    resolve_episode(&state, &tv_channel, &tv_show, &episode, Quality::Best).await?;
+                serde_json::to_string_pretty(&parts)?
+            }
+        };
+        Ok(json)
+    })
+}
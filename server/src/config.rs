@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use crate::fetcher::FixtureMode;
+
+const ENV_PREFIX: &str = "TV_SHOWS_";
+
+/// Config file kept in the cache folder by the mobile app, the cleanup leaves it alone.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// All the knobs of the server which used to be compile time constants.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Home page of the source site.
+    pub desi_tv: String,
+    /// Other domains of the source site, tried when `desi_tv` stops working.
+    pub mirrors: Vec<String>,
+    /// Icon used when a tv show or a channel doesn't have one.
+    pub no_icon: String,
+    pub banned_channels: Vec<String>,
+    /// Number of channel rows at the bottom of the home page, which are listed separately.
+    pub no_of_channel_rows: usize,
+    /// Number of parallel http requests made to the source site.
+    pub parallelism: usize,
+    /// How long the cached channels and metadata files stay valid, in seconds.
+    pub expiry_secs: u64,
+    /// How long the episodes of a tv show are served before its first page is checked again.
+    pub show_refresh_secs: u64,
+    /// How long before their expiry the channels are downloaded again by the scheduler.
+    pub channels_prefetch_secs: u64,
+    /// How often the scheduler warms the cache of the recently opened tv shows.
+    pub warm_interval_secs: u64,
+    /// Tv shows opened within this many seconds are kept warm.
+    pub warm_window_secs: u64,
+    /// Number of tv shows warmed at the same time, 0 turns the warming off.
+    pub warm_parallelism: usize,
+    /// How often the favorite tv shows are checked for new episodes.
+    pub feed_interval_secs: u64,
+    pub user_agent: String,
+    /// Channel title => logo url.
+    pub logo_map: HashMap<String, String>,
+    /// Whether the scraped pages are recorded into or replayed from `fixtures_dir`.
+    pub fixture_mode: FixtureMode,
+    pub fixtures_dir: PathBuf,
+    /// `.toml` or `.json` file overriding the css selectors of the source site.
+    pub selector_profile: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            desi_tv: "https://www.yodesitv.info".into(),
+            mirrors: Vec::new(),
+            no_icon: "https://www.yodesitv.info/wp-content/uploads/2016/11/no-thumbnail-370x208.jpg".into(),
+            banned_channels: ["Star Jalsha", "Star Pravah", "Star Vijay", "Bindass TV"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            no_of_channel_rows: 2,
+            parallelism: 8,
+            expiry_secs: 2 * 24 * 60 * 60,
+            show_refresh_secs: 3 * 60 * 60,
+            channels_prefetch_secs: 30 * 60,
+            warm_interval_secs: 60 * 60,
+            warm_window_secs: 3 * 24 * 60 * 60,
+            warm_parallelism: 2,
+            feed_interval_secs: 60 * 60,
+            user_agent: "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:121.0) Gecko/20100101 Firefox/121.0".into(),
+            logo_map: [
+                ("Star Plus", "https://static.wikia.nocookie.net/logopedia/images/3/32/StarPlus_logo_%282018%29.png/revision/latest/scale-to-width-down/200?cb=20201128160713"),
+                ("Colors", "https://static.wikia.nocookie.net/logopedia/images/f/fb/Colors_2016.svg/revision/latest/scale-to-width-down/250?cb=20210207143131"),
+                ("Zee TV", "https://static.wikia.nocookie.net/logopedia/images/d/dd/Zee_TV_2017.svg/revision/latest/scale-to-width-down/200?cb=20191222192526"),
+                ("Sony TV", "https://www.pngfind.com/pngs/m/50-505569_sony-tv-logo-png-channel-sony-entertainment-television.png"),
+                ("& TV", "https://static.wikia.nocookie.net/logopedia/images/8/8c/%26TV.jpg/revision/latest/scale-to-width-down/220?cb=20161205163128"),
+                ("Sab TV", "https://static.wikia.nocookie.net/logopedia/images/1/18/SONY_SAB_SD.png/revision/latest/scale-to-width-down/250?cb=20221023220045"),
+                ("Star Bharat", "https://static.wikia.nocookie.net/logopedia/images/7/7b/Star_Bharat_2022.png/revision/latest/scale-to-width-down/250?cb=20220802114101"),
+                ("ALT Balaji", "https://static.wikia.nocookie.net/logopedia/images/b/b9/Alt_Balaji.jpg/revision/latest/scale-to-width-down/250?cb=20200222162603"),
+                ("Amazon", "https://www.yodesitv.info/wp-content/uploads/2020/04/amazonvideo-768x432.png"),
+                ("Hotstar", "https://static.wikia.nocookie.net/logopedia/images/e/e9/Disney%2B_Hotstar.svg/revision/latest/scale-to-width-down/300?cb=20220114145230"),
+                ("Netflix", "https://static.wikia.nocookie.net/logopedia/images/5/5d/Netflix_2014.svg/revision/latest/scale-to-width-down/250?cb=20201124111638"),
+                ("Zee5", "https://static.wikia.nocookie.net/logopedia/images/d/d0/Zee5.svg/revision/latest?cb=20210807175347"),
+                ("VOOT Web Series", "https://www.yodesitv.info/wp-content/uploads/2020/04/voot-370x208.jpg"),
+                ("Hoichoi Web Series", "https://www.yodesitv.info/wp-content/uploads/2020/04/hoichoi-768x432.png"),
+                ("MX Web Series", "https://www.yodesitv.info/wp-content/uploads/2020/04/mx-370x208.jpg"),
+                ("Vikram Bhatt Web Series", "https://www.yodesitv.info/wp-content/uploads/2020/04/vikram-370x208.jpg"),
+                ("Eros NOW Web Series", "https://www.yodesitv.info/wp-content/uploads/2020/05/erosi-370x208.jpg"),
+            ]
+            .into_iter()
+            .map(|(title, logo)| (title.to_owned(), logo.to_owned()))
+            .collect(),
+            fixture_mode: FixtureMode::Off,
+            fixtures_dir: PathBuf::from("fixtures"),
+            selector_profile: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from a `.toml` or `.json` file (if given) and applies the env overrides on top.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        let config = match path {
+            Some(path) => {
+                info!("Loading config from {path:?}");
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Couldn't read config file {path:?}"))?;
+                match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("json") => serde_json::from_str(&content)?,
+                    Some("toml") => toml::from_str(&content)?,
+                    _ => return Err(anyhow!("Unsupported config file: {path:?}")),
+                }
+            }
+            None => Config::default(),
+        };
+        config.with_env_overrides()
+    }
+
+    /// Overrides the fields with `TV_SHOWS_<FIELD_NAME>` env variables, lists are comma separated.
+    pub fn with_env_overrides(mut self) -> anyhow::Result<Config> {
+        fn read_env<T: FromStr>(name: &str, field: &mut T) -> anyhow::Result<()>
+        where
+            T::Err: std::fmt::Display,
+        {
+            let key = format!("{ENV_PREFIX}{name}");
+            if let Ok(value) = env::var(&key) {
+                debug!("Overriding config with {key}={value}");
+                *field = value
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid value for {key}: {e}"))?;
+            }
+            Ok(())
+        }
+
+        fn read_list_env(name: &str, field: &mut Vec<String>) -> anyhow::Result<()> {
+            let mut value = String::new();
+            read_env(name, &mut value)?;
+            if !value.is_empty() {
+                *field = value
+                    .split(',')
+                    .map(|item| item.trim().to_owned())
+                    .filter(|item| !item.is_empty())
+                    .collect();
+            }
+            Ok(())
+        }
+
+        read_env("DESI_TV", &mut self.desi_tv)?;
+        read_env("NO_ICON", &mut self.no_icon)?;
+        read_env("NO_OF_CHANNEL_ROWS", &mut self.no_of_channel_rows)?;
+        read_env("PARALLELISM", &mut self.parallelism)?;
+        read_env("EXPIRY_SECS", &mut self.expiry_secs)?;
+        read_env("SHOW_REFRESH_SECS", &mut self.show_refresh_secs)?;
+        read_env("CHANNELS_PREFETCH_SECS", &mut self.channels_prefetch_secs)?;
+        read_env("WARM_INTERVAL_SECS", &mut self.warm_interval_secs)?;
+        read_env("WARM_WINDOW_SECS", &mut self.warm_window_secs)?;
+        read_env("WARM_PARALLELISM", &mut self.warm_parallelism)?;
+        read_env("FEED_INTERVAL_SECS", &mut self.feed_interval_secs)?;
+        read_env("USER_AGENT", &mut self.user_agent)?;
+        read_env("FIXTURE_MODE", &mut self.fixture_mode)?;
+        read_env("FIXTURES_DIR", &mut self.fixtures_dir)?;
+        let mut selector_profile = PathBuf::new();
+        read_env("SELECTOR_PROFILE", &mut selector_profile)?;
+        if !selector_profile.as_os_str().is_empty() {
+            self.selector_profile = Some(selector_profile);
+        }
+        read_list_env("BANNED_CHANNELS", &mut self.banned_channels)?;
+        read_list_env("MIRRORS", &mut self.mirrors)?;
+        Ok(self)
+    }
+
+    pub fn expiry(&self) -> Duration {
+        Duration::from_secs(self.expiry_secs)
+    }
+
+    pub fn show_refresh(&self) -> Duration {
+        Duration::from_secs(self.show_refresh_secs)
+    }
+
+    pub fn channels_prefetch(&self) -> Duration {
+        Duration::from_secs(self.channels_prefetch_secs)
+    }
+
+    pub fn warm_interval(&self) -> Duration {
+        Duration::from_secs(self.warm_interval_secs)
+    }
+
+    pub fn warm_window(&self) -> Duration {
+        Duration::from_secs(self.warm_window_secs)
+    }
+
+    pub fn feed_interval(&self) -> Duration {
+        Duration::from_secs(self.feed_interval_secs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+
+    #[test]
+    fn test_partial_config() {
+        let config = toml::from_str::<Config>(
+            r#"
+            desi_tv = "https://www.desitellybox.me"
+            banned_channels = ["Colors"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.desi_tv, "https://www.desitellybox.me");
+        assert_eq!(config.banned_channels, vec!["Colors"]);
+        assert_eq!(config.parallelism, Config::default().parallelism);
+
+        let config = serde_json::from_str::<Config>(r#"{ "parallelism": 2 }"#).unwrap();
+        assert_eq!(config.parallelism, 2);
+        assert!(config.logo_map.contains_key("Star Plus"));
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::NaiveDate;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+use crate::models::{EpisodeInfo, TvEpisode};
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// "10th September 2022", "10 Sept, 2022"
+static DAY_MONTH_YEAR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(\d{1,2})(?:st|nd|rd|th)?\s+([a-z]{3,9})\.?,?\s+(\d{4})\b").unwrap()
+});
+
+/// "September 10th, 2022"
+static MONTH_DAY_YEAR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b([a-z]{3,9})\.?\s+(\d{1,2})(?:st|nd|rd|th)?,?\s+(\d{4})\b").unwrap()
+});
+
+/// "10-09-2022", "10.09.2022", "10/09/2022"
+static NUMERIC_DATE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(\d{1,2})[-./](\d{1,2})[-./](\d{4})\b").unwrap());
+
+/// "Episode 12", "Ep. 12", "Ep #12"
+static EPISODE_NUMBER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(?:episode|ep)\.?\s*#?\s*(\d{1,5})\b").unwrap());
+
+impl EpisodeInfo {
+    pub fn parse(show: &str, episode: &TvEpisode) -> EpisodeInfo {
+        let TvEpisode { id, title, parts } = episode;
+        let mut providers = Vec::new();
+        for episode in parts {
+            if !providers.contains(&episode.provider) {
+                providers.push(episode.provider);
+            }
+        }
+        EpisodeInfo {
+            id: id.clone(),
+            title: title.clone(),
+            show: show.to_owned(),
+            date: parse_date(title).map(|date| date.format("%Y-%m-%d").to_string()),
+            number: EPISODE_NUMBER
+                .captures(title)
+                .and_then(|c| c[1].parse().ok()),
+            part_count: parts.iter().map(|e| e.links.len()).max().unwrap_or(0),
+            providers,
+        }
+    }
+}
+
+/// Finds the air date in an episode title, the site writes it in a handful of ways.
+pub fn parse_date(title: &str) -> Option<NaiveDate> {
+    fn date(year: &str, month: Option<u32>, day: &str) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(year.parse().ok()?, month?, day.parse().ok()?)
+    }
+
+    let dmy = |c: Captures| date(&c[3], month(&c[2]), &c[1]);
+    let mdy = |c: Captures| date(&c[3], month(&c[1]), &c[2]);
+    let numeric = |c: Captures| date(&c[3], c[2].parse().ok(), &c[1]);
+    DAY_MONTH_YEAR
+        .captures_iter(title)
+        .find_map(dmy)
+        .or_else(|| MONTH_DAY_YEAR.captures_iter(title).find_map(mdy))
+        .or_else(|| NUMERIC_DATE.captures_iter(title).find_map(numeric))
+}
+
+fn month(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    let prefix = name.get(..3)?;
+    let idx = MONTHS.iter().position(|&m| m == prefix)?;
+    // Rules out words like "Marriage", which only share the prefix of a month.
+    let full = chrono::Month::try_from(idx as u8 + 1)
+        .ok()?
+        .name()
+        .to_lowercase();
+    if full.starts_with(&name) || name == "sept" {
+        Some(idx as u32 + 1)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use crate::models::{Episode, EpisodeInfo, TvEpisode, VideoProvider};
+
+    use super::parse_date;
+
+    #[test]
+    fn test_parse_date() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d);
+        assert_eq!(parse_date("Naagini 10th September 2022"), date(2022, 9, 10));
+        assert_eq!(parse_date("Anupamaa 1st Oct 2023"), date(2023, 10, 1));
+        assert_eq!(parse_date("Imlie 3 Sept, 2021"), date(2021, 9, 3));
+        assert_eq!(parse_date("Imlie May 22nd, 2021"), date(2021, 5, 22));
+        assert_eq!(parse_date("Bigg Boss 17 – 05-11-2023"), date(2023, 11, 5));
+        assert_eq!(parse_date("Bigg Boss 17 – 31st February 2023"), None);
+        assert_eq!(parse_date("Marriage 2022 Special"), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let parts = vec![
+            Episode {
+                provider: VideoProvider::TVLogy,
+                links: vec![("Part 1".into(), "a".into()), ("Part 2".into(), "b".into())],
+            },
+            Episode {
+                provider: VideoProvider::FlashPlayer,
+                links: vec![("Part 1".into(), "c".into())],
+            },
+        ];
+        let episode = |title: &str| TvEpisode {
+            id: "id".into(),
+            title: title.into(),
+            parts: parts.clone(),
+        };
+        let info = EpisodeInfo::parse("Whos Your Daddy", &episode("Whos Your Daddy Episode 12"));
+        assert_eq!(info.date, None);
+        assert_eq!(info.number, Some(12));
+        assert_eq!(info.part_count, 2);
+        assert_eq!(
+            info.providers,
+            vec![VideoProvider::TVLogy, VideoProvider::FlashPlayer]
+        );
+
+        let info = EpisodeInfo::parse("Naagini", &episode("Naagini 10th September 2022"));
+        assert_eq!(info.date.as_deref(), Some("2022-09-10"));
+        assert_eq!(info.number, None);
+    }
+}
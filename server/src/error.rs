@@ -1,30 +1,201 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+use axum::Json;
 use serde_json::json;
+use tokio::time::error::Elapsed;
 use tracing::*;
 
+/// Failures which the clients can tell apart by the `code` of the error response.
+#[derive(Debug)]
+pub enum ServerError {
+    ShowNotFound(String),
+    EpisodeNotFound(String),
+    Upstream(String),
+    Parse(String),
+    ProviderResolution(String),
+    Timeout(String),
+    NotInitialized(String),
+    InvalidInput(String),
+    /// The same work is running already.
+    Busy(String),
+}
+
+impl ServerError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ServerError::ShowNotFound(_) => StatusCode::NOT_FOUND,
+            ServerError::EpisodeNotFound(_) => StatusCode::NOT_FOUND,
+            ServerError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ServerError::Parse(_) => StatusCode::BAD_GATEWAY,
+            ServerError::ProviderResolution(_) => StatusCode::BAD_GATEWAY,
+            ServerError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ServerError::NotInitialized(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ServerError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            ServerError::Busy(_) => StatusCode::CONFLICT,
+        }
+    }
+
+    /// Stable, machine readable name of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServerError::ShowNotFound(_) => "show_not_found",
+            ServerError::EpisodeNotFound(_) => "episode_not_found",
+            ServerError::Upstream(_) => "upstream_error",
+            ServerError::Parse(_) => "parse_error",
+            ServerError::ProviderResolution(_) => "provider_failed",
+            ServerError::Timeout(_) => "timeout",
+            ServerError::NotInitialized(_) => "not_initialized",
+            ServerError::InvalidInput(_) => "invalid_input",
+            ServerError::Busy(_) => "busy",
+        }
+    }
+}
+
+impl Display for ServerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::ShowNotFound(msg)
+            | ServerError::EpisodeNotFound(msg)
+            | ServerError::Upstream(msg)
+            | ServerError::Parse(msg)
+            | ServerError::ProviderResolution(msg)
+            | ServerError::Timeout(msg)
+            | ServerError::NotInitialized(msg)
+            | ServerError::InvalidInput(msg)
+            | ServerError::Busy(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
 pub struct HttpError {
     inner: anyhow::Error,
 }
 
+impl HttpError {
+    /// Finds the most specific status & code in the error chain, anything unknown is a 500.
+    fn status_and_code(&self) -> (StatusCode, &'static str) {
+        for cause in self.inner.chain() {
+            if let Some(e) = cause.downcast_ref::<ServerError>() {
+                return (e.status(), e.code());
+            }
+        }
+        for cause in self.inner.chain() {
+            if cause.is::<Elapsed>() {
+                return (StatusCode::GATEWAY_TIMEOUT, "timeout");
+            }
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                return if e.is_timeout() {
+                    (StatusCode::GATEWAY_TIMEOUT, "timeout")
+                } else {
+                    (StatusCode::BAD_GATEWAY, "upstream_error")
+                };
+            }
+        }
+        (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+    }
+}
+
 impl From<anyhow::Error> for HttpError {
     fn from(inner: anyhow::Error) -> Self {
         HttpError { inner }
     }
 }
 
+impl From<ServerError> for HttpError {
+    fn from(e: ServerError) -> Self {
+        HttpError { inner: e.into() }
+    }
+}
+
 impl IntoResponse for HttpError {
     fn into_response(self) -> Response {
+        let (status, code) = self.status_and_code();
         let json = json!({
             "error": self.inner.to_string(),
+            "code": code,
         });
-        /*info!(
-            "Returning http error: {json}, for error: {:#?}",
-            self.inner.backtrace()
-        );*/
-        info!("Returning http error: {json}");
-        let response = serde_json::to_string_pretty(&json)
-            .unwrap_or_else(|_| format!("Something is wrong: {}", self.inner));
-        (StatusCode::INTERNAL_SERVER_ERROR, response).into_response()
+        info!("Returning http error: {status} {json}");
+        (status, Json(json)).into_response()
+    }
+}
+
+/// A param of the path of a request, it's only missing when the route doesn't declare it.
+pub fn path_param<'a>(params: &'a HashMap<String, String>, name: &str) -> anyhow::Result<&'a str> {
+    params
+        .get(name)
+        .map(String::as_str)
+        .ok_or_else(|| anyhow!("Path didn't contain {name}"))
+}
+
+/// The `tv_channel` & `tv_show` of the path of a request.
+pub fn show_path(params: &HashMap<String, String>) -> anyhow::Result<(&str, &str)> {
+    Ok((
+        path_param(params, "tv_channel")?,
+        path_param(params, "tv_show")?,
+    ))
+}
+
+/// A query param parsed into `T`, or `default` when it isn't given.
+pub fn query_param<T: FromStr>(
+    params: &HashMap<String, String>,
+    name: &str,
+    default: T,
+) -> Result<T, ServerError> {
+    match params.get(name) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| ServerError::InvalidInput(format!("Invalid {name} '{value}'"))),
+        None => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Context;
+    use axum::http::{header, StatusCode};
+    use axum::response::IntoResponse;
+
+    use std::collections::HashMap;
+
+    use super::{query_param, show_path, HttpError, ServerError};
+
+    #[test]
+    fn test_status_code() {
+        let error: anyhow::Result<()> = Err(ServerError::ShowNotFound("Naagin".into()).into());
+        let error = HttpError::from(error.context("Loading episodes failed").unwrap_err());
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+
+        let error = HttpError::from(ServerError::Timeout("Too slow".into()));
+        assert_eq!(error.into_response().status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let error = HttpError::from(anyhow::anyhow!("Something else"));
+        assert_eq!(
+            error.into_response().status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn test_params() {
+        let params = HashMap::from([
+            ("tv_channel".to_owned(), "star-plus".to_owned()),
+            ("tv_show".to_owned(), "anupamaa".to_owned()),
+            ("limit".to_owned(), "ten".to_owned()),
+        ]);
+        assert_eq!(show_path(&params).unwrap(), ("star-plus", "anupamaa"));
+        assert!(show_path(&HashMap::new()).is_err());
+        assert!(!query_param(&params, "load_more", false).unwrap());
+        let error = query_param(&params, "limit", 20usize).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid limit 'ten'");
     }
 }
@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Instant, SystemTime};
+
+use axum::extract::{Path, Query, State};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::*;
+
+use crate::app_state::AppState;
+use crate::error::{query_param, show_path, HttpError, ServerError};
+use crate::models::{EpisodeInfo, TvEpisode, TvShow};
+use crate::profiles::{default_profile, CurrentProfile};
+use crate::store::KeyedStateWrapper;
+use crate::tv_shows::{cached_episodes, tv_show_episodes};
+use crate::utils::{slugify, to_rfc3339, url_id};
+
+/// Favorites, by [`favorite_key`].
+pub type FavoritesStateWrapper = KeyedStateWrapper<Favorite>;
+
+const DEFAULT_LIMIT: usize = 50;
+
+/// New episodes remembered for every favorite, the older ones drop out of the feed.
+const MAX_NEW_EPISODES: usize = 50;
+
+/// A tv show whose new episodes are collected into the feed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Favorite {
+    #[serde(default = "default_profile")]
+    pub profile: String,
+    /// Title or id of the channel the show was added from, to find the show again.
+    pub channel: String,
+    pub channel_id: String,
+    pub show_id: String,
+    pub tv_show: TvShow,
+    pub added_at: SystemTime,
+    /// Ids of the episodes known at the last check, anything above them is new.
+    pub seen: HashSet<String>,
+    /// Newest first.
+    pub new_episodes: Vec<NewEpisode>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NewEpisode {
+    pub id: String,
+    pub title: String,
+    /// Air date as `YYYY-MM-DD`, parsed out of the title.
+    pub date: Option<String>,
+    pub seen_at: SystemTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FavoriteResponse {
+    channel_id: String,
+    show_id: String,
+    title: String,
+    icon: String,
+    added_at: String,
+    new_episodes: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedItem {
+    channel_id: String,
+    show_id: String,
+    show: String,
+    episode_id: String,
+    episode: String,
+    date: Option<String>,
+    seen_at: String,
+}
+
+impl Favorite {
+    pub fn key(&self) -> String {
+        favorite_key(&self.profile, &self.channel_id, &self.show_id)
+    }
+
+    /// Records the episodes above the ones seen before, returns how many there were.
+    fn update(&mut self, episodes: &[TvEpisode]) -> usize {
+        // Without anything seen before, everything is only the starting point.
+        let fresh = if self.seen.is_empty() {
+            Vec::new()
+        } else {
+            episodes
+                .iter()
+                .take_while(|episode| !self.seen.contains(&episode.id))
+                .collect::<Vec<_>>()
+        };
+        let now = SystemTime::now();
+        let new_episodes = fresh.iter().map(|episode| NewEpisode {
+            id: episode.id.clone(),
+            title: episode.title.clone(),
+            date: EpisodeInfo::parse(&self.tv_show.title, episode).date,
+            seen_at: now,
+        });
+        self.new_episodes.splice(0..0, new_episodes);
+        self.new_episodes.truncate(MAX_NEW_EPISODES);
+        self.seen = episodes.iter().map(|episode| episode.id.clone()).collect();
+        fresh.len()
+    }
+
+    fn to_res(&self) -> FavoriteResponse {
+        FavoriteResponse {
+            channel_id: self.channel_id.clone(),
+            show_id: self.show_id.clone(),
+            title: self.tv_show.title.clone(),
+            icon: self.tv_show.icon.clone(),
+            added_at: to_rfc3339(self.added_at),
+            new_episodes: self.new_episodes.len(),
+        }
+    }
+}
+
+pub fn favorite_key(profile: &str, channel_id: &str, show_id: &str) -> String {
+    format!("{profile}/{channel_id}/{show_id}")
+}
+
+pub async fn list_favorites(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+) -> Result<impl IntoResponse, HttpError> {
+    let mut favorites = state.favorites.filter(|f| f.profile == profile.id).await;
+    favorites.sort_by(|f1, f2| f1.tv_show.title.cmp(&f2.tv_show.title));
+    Ok(Json(
+        favorites.iter().map(|f| f.to_res()).collect::<Vec<_>>(),
+    ))
+}
+
+pub async fn add_favorite(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let (tv_channel, tv_show) = show_path(&params)?;
+    let soap = state
+        .tv_channels
+        .get_tv_show(tv_channel, tv_show)
+        .await
+        .ok_or_else(|| {
+            ServerError::ShowNotFound(format!("Couldn't find Soap with {tv_channel} & {tv_show}"))
+        })?;
+    let channel_id = slugify(tv_channel);
+    let show_id = url_id(&soap.url);
+    let key = favorite_key(&profile.id, &channel_id, &show_id);
+    if let Some(favorite) = state.favorites.get(&key).await {
+        return Ok(Json(favorite.to_res()));
+    }
+
+    // The episodes listed now are the ones which aren't new.
+    tv_show_episodes(&state, tv_channel, &show_id, false).await?;
+    let episodes = cached_episodes(&state, tv_channel, &show_id)
+        .await
+        .unwrap_or_default();
+    let mut favorite = Favorite {
+        profile: profile.id,
+        channel: tv_channel.to_owned(),
+        channel_id,
+        show_id,
+        tv_show: soap,
+        added_at: SystemTime::now(),
+        seen: HashSet::new(),
+        new_episodes: Vec::new(),
+    };
+    favorite.update(&episodes);
+    info!("Added '{}' to the favorites", favorite.tv_show.title);
+    let response = favorite.to_res();
+    state.favorites.put(key, favorite).await;
+    Ok(Json(response))
+}
+
+pub async fn remove_favorite(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let (tv_channel, tv_show) = show_path(&params)?;
+    let show_id = match state.tv_channels.get_tv_show(tv_channel, tv_show).await {
+        Some(soap) => url_id(&soap.url),
+        None => tv_show.to_owned(),
+    };
+    let key = favorite_key(&profile.id, &slugify(tv_channel), &show_id);
+    let removed = state.favorites.remove(&key).await;
+    Ok(Json(json!({ "removed": removed })))
+}
+
+/// New episodes of all the favorites, the latest air dates first.
+pub async fn feed(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let limit = query_param(&params, "limit", DEFAULT_LIMIT)?;
+    let favorites = state.favorites.filter(|f| f.profile == profile.id).await;
+    Ok(Json(feed_items(&favorites, limit)))
+}
+
+/// Checks the favorites of every profile for new episodes, it's run by the scheduler.
+pub async fn check_favorites(state: &AppState) {
+    let favorites = state.favorites.all().await;
+    if favorites.is_empty() {
+        return;
+    }
+    let start = Instant::now();
+    info!("Checking {} favorites for new episodes", favorites.len());
+    for favorite in favorites {
+        let Favorite {
+            channel, show_id, ..
+        } = &favorite;
+        if let Err(e) = tv_show_episodes(state, channel, show_id, false).await {
+            warn!("Failed to check '{}': {e:?}", favorite.tv_show.title);
+            continue;
+        }
+        let Some(episodes) = cached_episodes(state, channel, show_id).await else {
+            continue;
+        };
+        let key = favorite.key();
+        let title = &favorite.tv_show.title;
+        state
+            .favorites
+            .update(&key, |favorite| {
+                let found = favorite.update(&episodes);
+                if found > 0 {
+                    info!("Found {found} new episodes of '{title}'");
+                }
+            })
+            .await;
+    }
+    info!("Time taken to check the favorites: {:?}", start.elapsed());
+}
+
+fn feed_items(favorites: &[Favorite], limit: usize) -> Vec<FeedItem> {
+    let mut items = favorites
+        .iter()
+        .flat_map(|favorite| {
+            favorite.new_episodes.iter().map(move |episode| {
+                let item = FeedItem {
+                    channel_id: favorite.channel_id.clone(),
+                    show_id: favorite.show_id.clone(),
+                    show: favorite.tv_show.title.clone(),
+                    episode_id: episode.id.clone(),
+                    episode: episode.title.clone(),
+                    date: episode.date.clone(),
+                    seen_at: to_rfc3339(episode.seen_at),
+                };
+                (episode.seen_at, item)
+            })
+        })
+        .collect::<Vec<_>>();
+    // The episodes without a date go after the dated ones.
+    items.sort_by(|(seen1, item1), (seen2, item2)| {
+        item2.date.cmp(&item1.date).then_with(|| seen2.cmp(seen1))
+    });
+    items
+        .into_iter()
+        .take(limit)
+        .map(|(_, item)| item)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::time::SystemTime;
+
+    use crate::models::{TvEpisode, TvShow};
+
+    use super::{feed_items, Favorite};
+
+    fn episodes(titles: &[&str]) -> Vec<TvEpisode> {
+        titles
+            .iter()
+            .map(|&title| TvEpisode {
+                id: title.to_lowercase().replace(' ', "-"),
+                title: title.to_owned(),
+                parts: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn favorite(title: &str) -> Favorite {
+        Favorite {
+            profile: "default".into(),
+            channel: "Star Plus".into(),
+            channel_id: "star-plus".into(),
+            show_id: title.to_lowercase(),
+            tv_show: TvShow {
+                title: title.into(),
+                url: format!("https://example.com/{title}/"),
+                icon: "icon".into(),
+            },
+            added_at: SystemTime::now(),
+            seen: HashSet::new(),
+            new_episodes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_feed() {
+        let mut anupamaa = favorite("Anupamaa");
+        assert_eq!(anupamaa.update(&episodes(&["Anupamaa 1st Oct 2023"])), 0);
+        let found = anupamaa.update(&episodes(&[
+            "Anupamaa 3rd Oct 2023",
+            "Anupamaa 2nd Oct 2023",
+            "Anupamaa 1st Oct 2023",
+            // Loaded from an older page.
+            "Anupamaa 30th Sep 2023",
+        ]));
+        assert_eq!(found, 2);
+
+        let mut imlie = favorite("Imlie");
+        imlie.update(&episodes(&["Imlie Special"]));
+        imlie.update(&episodes(&[
+            "Imlie 2nd Oct 2023",
+            "Imlie Special Episode",
+            "Imlie Special",
+        ]));
+
+        let titles = feed_items(&[imlie, anupamaa], 10)
+            .into_iter()
+            .map(|item| item.episode)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            vec![
+                "Anupamaa 3rd Oct 2023",
+                "Imlie 2nd Oct 2023",
+                "Anupamaa 2nd Oct 2023",
+                "Imlie Special Episode",
+            ]
+        );
+    }
+}
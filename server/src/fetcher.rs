@@ -0,0 +1,338 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Context;
+use reqwest::header::HeaderName;
+use reqwest::{Client, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::*;
+
+use crate::config::Config;
+use crate::error::ServerError;
+use crate::metrics::MeteredSend;
+use crate::utils::hash;
+
+/// What the scraping requests do with the fixtures folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FixtureMode {
+    /// Fixtures are neither read nor written.
+    #[default]
+    Off,
+    /// Every upstream response is saved as a fixture.
+    Record,
+    /// Responses are served from the fixtures, without touching the network.
+    Replay,
+}
+
+impl FromStr for FixtureMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(FixtureMode::Off),
+            "record" => Ok(FixtureMode::Record),
+            "replay" => Ok(FixtureMode::Replay),
+            _ => Err(format!("Unknown fixture mode '{s}'")),
+        }
+    }
+}
+
+/// An upstream response as it's saved in the fixtures folder.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Fixture {
+    pub method: String,
+    pub url: String,
+    /// Url of the response, after the redirects.
+    pub landed_url: String,
+    pub status: u16,
+    pub body: String,
+}
+
+impl Fixture {
+    pub fn file_name(method: &Method, url: &str) -> String {
+        format!("{}.json", hash(format!("{method} {url}")))
+    }
+
+    pub async fn save(&self, folder: &Path) -> anyhow::Result<()> {
+        let method = Method::from_str(&self.method)?;
+        let file = folder.join(Fixture::file_name(&method, &self.url));
+        fs::create_dir_all(folder).await?;
+        fs::write(&file, serde_json::to_string_pretty(self)?).await?;
+        Ok(())
+    }
+}
+
+/// Text of an upstream page.
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub landed_url: String,
+    pub status: StatusCode,
+    pub text: String,
+}
+
+/// Downloads the pages which the scrapers parse, recording or replaying them as configured.
+///
+/// The `/media` & `/logo` proxies stream binary content, they use the plain http client instead.
+#[derive(Clone)]
+pub struct Fetcher {
+    client: Client,
+    mode: FixtureMode,
+    fixtures: Arc<Path>,
+}
+
+impl Fetcher {
+    pub fn new(client: Client, config: &Config) -> Fetcher {
+        if config.fixture_mode != FixtureMode::Off {
+            info!(
+                "Fixtures are in {:?} mode at {:?}",
+                config.fixture_mode, config.fixtures_dir
+            );
+        }
+        Fetcher {
+            client,
+            mode: config.fixture_mode,
+            fixtures: Arc::from(config.fixtures_dir.as_path()),
+        }
+    }
+
+    pub fn get(&self, url: &str) -> FetchRequest<'_> {
+        self.request(Method::GET, url)
+    }
+
+    pub fn post(&self, url: &str) -> FetchRequest<'_> {
+        self.request(Method::POST, url)
+    }
+
+    fn request(&self, method: Method, url: &str) -> FetchRequest<'_> {
+        FetchRequest {
+            fetcher: self,
+            method,
+            url: url.to_owned(),
+            headers: Vec::new(),
+        }
+    }
+
+    async fn fetch(&self, request: FetchRequest<'_>) -> anyhow::Result<Page> {
+        let FetchRequest {
+            method,
+            url,
+            headers,
+            ..
+        } = request;
+        if self.mode == FixtureMode::Replay {
+            return self.replay(&method, &url).await;
+        }
+
+        let mut builder = self.client.request(method.clone(), &url);
+        for (key, value) in headers {
+            builder = builder.header(key, value);
+        }
+        let response = builder.metered_send().await?;
+        let page = Page {
+            landed_url: response.url().to_string(),
+            status: response.status(),
+            text: response.text().await?,
+        };
+        if self.mode == FixtureMode::Record {
+            let fixture = Fixture {
+                method: method.to_string(),
+                url,
+                landed_url: page.landed_url.clone(),
+                status: page.status.as_u16(),
+                body: page.text.clone(),
+            };
+            if let Err(e) = fixture.save(&self.fixtures).await {
+                warn!("Failed to record the fixture of {}: {e:?}", fixture.url);
+            }
+        }
+        Ok(page)
+    }
+
+    async fn replay(&self, method: &Method, url: &str) -> anyhow::Result<Page> {
+        let file = self.fixtures.join(Fixture::file_name(method, url));
+        debug!("Replaying {method} {url} from {file:?}");
+        let content = fs::read_to_string(&file)
+            .await
+            .with_context(|| format!("No fixture for {method} {url}"))?;
+        let fixture = serde_json::from_str::<Fixture>(&content)?;
+        Ok(Page {
+            landed_url: fixture.landed_url,
+            status: StatusCode::from_u16(fixture.status)?,
+            text: fixture.body,
+        })
+    }
+}
+
+pub struct FetchRequest<'a> {
+    fetcher: &'a Fetcher,
+    method: Method,
+    url: String,
+    headers: Vec<(HeaderName, String)>,
+}
+
+impl<'a> FetchRequest<'a> {
+    pub fn header(mut self, key: impl Into<HeaderName>, value: impl Into<String>) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+
+    /// Returns the page whatever its status is.
+    pub async fn page(self) -> anyhow::Result<Page> {
+        self.fetcher.fetch(self).await
+    }
+
+    pub async fn text(self) -> anyhow::Result<String> {
+        Ok(self.page().await?.text)
+    }
+
+    /// Returns the page, failing if it's not a success.
+    pub async fn success(self) -> anyhow::Result<Page> {
+        let url = self.url.clone();
+        let page = self.page().await?;
+        if !page.status.is_success() {
+            return Err(ServerError::Upstream(format!("{url} returned {}", page.status)).into());
+        }
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use reqwest::Method;
+
+    use crate::app_state::AppState;
+    use crate::config::Config;
+    use crate::profiles::Quality;
+    use crate::tv_channels::tv_channels;
+    use crate::tv_episodes::resolve_episode;
+    use crate::tv_shows::tv_show_episodes;
+    use crate::utils::TestDir;
+
+    use super::{Fixture, FixtureMode};
+
+    async fn save(folder: &Path, method: Method, url: &str, body: &str) {
+        let fixture = Fixture {
+            method: method.to_string(),
+            url: url.to_owned(),
+            landed_url: url.to_owned(),
+            status: 200,
+            body: body.to_owned(),
+        };
+        fixture.save(folder).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_pipeline() {
+        let test_dir = TestDir::new("fixtures");
+        let fixtures = test_dir.join("fixtures");
+        let site = "https://www.yodesitv.info";
+        save(
+            &fixtures,
+            Method::GET,
+            site,
+            r#"
+            <div class="post"><div class="single_page"><div class="post-content">
+                <div class="home-channel-title"><p>Star Plus</p></div>
+                <div class="one_sixth column-last"><a href="/star-plus/">Star Plus</a></div>
+            </div></div></div>"#,
+        )
+        .await;
+        save(
+            &fixtures,
+            Method::GET,
+            &format!("{site}/star-plus/"),
+            r#"
+            <div class="tab_container"><div id="tab-0-title-1"><div class="one_fourth">
+                <a href="/anupamaa/"><img src="/anupamaa.jpg"></a>
+                <p class="small-title"><a href="/anupamaa/">Anupamaa</a></p>
+            </div></div></div>"#,
+        )
+        .await;
+        save(
+            &fixtures,
+            Method::GET,
+            &format!("{site}/anupamaa/"),
+            r#"
+            <div id="content_box"><article class="latestPost"><div class="latestPost-content">
+                <h2 class="title"><a href="/anupamaa-12th-october-2023/">Anupamaa</a></h2>
+            </div></article></div>"#,
+        )
+        .await;
+        save(&fixtures, Method::GET, &format!("{site}/anupamaa-12th-october-2023/"), r#"
+            <div class="post-single-content"><header><h1 class="title">Anupamaa 12th October 2023</h1></header></div>
+            <div class="thecontent">
+                <div class="buttons btn_green"><span class="single-heading">Flash Player</span></div><p><a href="https://flash.example/video/1">Part 1</a></p>
+            </div>"#).await;
+        save(
+            &fixtures,
+            Method::GET,
+            "https://flash.example/video/1",
+            r#"<iframe allowfullscreen src="https://flash.example/embed/1"></iframe>"#,
+        )
+        .await;
+        save(
+            &fixtures,
+            Method::GET,
+            "https://flash.example/embed/1",
+            r#"player.setup({ sources: {"file": "https://cdn.example/master.m3u8"} });"#,
+        )
+        .await;
+        save(
+            &fixtures,
+            Method::GET,
+            "https://cdn.example/master.m3u8",
+            "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=800000\n720p.m3u8",
+        )
+        .await;
+        save(
+            &fixtures,
+            Method::GET,
+            "https://cdn.example/720p.m3u8",
+            "#EXTM3U\n#EXTINF:10,\nsegment-1.ts\n#EXT-X-ENDLIST",
+        )
+        .await;
+
+        let config = Config {
+            no_of_channel_rows: 0,
+            fixture_mode: FixtureMode::Replay,
+            fixtures_dir: fixtures,
+            ..Config::default()
+        };
+        let state = AppState::init(&test_dir.join("cache"), config)
+            .await
+            .unwrap();
+
+        let channels = tv_channels(&state).await.unwrap();
+        assert_eq!(channels.keys().collect::<Vec<_>>(), vec!["Star Plus"]);
+        let episodes = tv_show_episodes(&state, "Star Plus", "Anupamaa", false)
+            .await
+            .unwrap();
+        let episodes = serde_json::to_value(episodes).unwrap();
+        assert_eq!(episodes["episodes"][0], "Anupamaa 12th October 2023");
+        assert_eq!(
+            episodes["episode_info"][0]["id"],
+            "anupamaa-12th-october-2023"
+        );
+
+        let parts = resolve_episode(
+            &state,
+            "star-plus",
+            "anupamaa",
+            "anupamaa-12th-october-2023",
+            Quality::Best,
+        )
+        .await
+        .unwrap();
+        assert_eq!(parts.len(), 1);
+        let (title, metadata_url) = &parts[0];
+        assert_eq!(title, "Part 1");
+        let metadata_file = metadata_url.trim_start_matches("/metadata/");
+        let playlist = std::fs::read_to_string(test_dir.join("cache").join(metadata_file)).unwrap();
+        assert!(playlist.contains("url=https%3A%2F%2Fcdn.example%2Fsegment-1.ts"));
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use axum::extract::{Path, Query, State};
+use axum::response::IntoResponse;
+use axum::Json;
+use linked_hash_map::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use crate::app_state::AppState;
+use crate::error::{path_param, query_param, show_path, HttpError, ServerError};
+use crate::models::TvEpisode;
+use crate::profiles::{default_profile, CurrentProfile};
+use crate::store::KeyedStateWrapper;
+use crate::tv_shows::get_episode;
+use crate::utils::{slugify, to_rfc3339, url_id};
+
+/// Watch history, by [`history_key`].
+pub type HistoryStateWrapper = KeyedStateWrapper<EpisodeHistory>;
+
+const DEFAULT_LIMIT: usize = 20;
+
+/// Share of a part which has to be played for it to count as watched, unless the client tells.
+const COMPLETED_RATIO: f64 = 0.95;
+
+/// Playback progress of one part of an episode, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct PartProgress {
+    pub position: f64,
+    pub duration: f64,
+    pub completed: bool,
+}
+
+/// What has been watched of an episode.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EpisodeHistory {
+    #[serde(default = "default_profile")]
+    pub profile: String,
+    pub channel_id: String,
+    pub show_id: String,
+    pub show: String,
+    pub episode_id: String,
+    pub episode: String,
+    /// Part title => progress, the part played last is at the back.
+    pub parts: LinkedHashMap<String, PartProgress>,
+    /// Whether the last part of the episode has been watched till the end, once.
+    pub completed: bool,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProgressRequest {
+    part: String,
+    position: f64,
+    duration: f64,
+    completed: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryResponse {
+    channel_id: String,
+    show_id: String,
+    show: String,
+    episode_id: String,
+    episode: String,
+    completed: bool,
+    /// The part to resume, with its progress.
+    part: String,
+    position: f64,
+    duration: f64,
+    parts: LinkedHashMap<String, PartProgress>,
+    updated_at: String,
+}
+
+impl EpisodeHistory {
+    pub fn key(&self) -> String {
+        history_key(
+            &self.profile,
+            &self.channel_id,
+            &self.show_id,
+            &self.episode_id,
+        )
+    }
+
+    fn record(&mut self, part: &str, progress: PartProgress, is_last_part: bool) {
+        self.parts.remove(part);
+        self.parts.insert(part.to_owned(), progress);
+        self.completed |= progress.completed && is_last_part;
+        self.updated_at = SystemTime::now();
+    }
+
+    fn to_res(&self) -> HistoryResponse {
+        let (part, progress) = self
+            .parts
+            .back()
+            .map(|(part, progress)| (part.clone(), *progress))
+            .unwrap_or_else(|| {
+                let progress = PartProgress {
+                    position: 0.0,
+                    duration: 0.0,
+                    completed: false,
+                };
+                (String::new(), progress)
+            });
+        HistoryResponse {
+            channel_id: self.channel_id.clone(),
+            show_id: self.show_id.clone(),
+            show: self.show.clone(),
+            episode_id: self.episode_id.clone(),
+            episode: self.episode.clone(),
+            completed: self.completed,
+            part,
+            position: progress.position,
+            duration: progress.duration,
+            parts: self.parts.clone(),
+            updated_at: to_rfc3339(self.updated_at),
+        }
+    }
+}
+
+pub fn history_key(profile: &str, channel_id: &str, show_id: &str, episode_id: &str) -> String {
+    format!("{profile}/{channel_id}/{show_id}/{episode_id}")
+}
+
+/// Records how far a part of an episode has been played.
+pub async fn record_progress(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+    Path(params): Path<HashMap<String, String>>,
+    Json(request): Json<ProgressRequest>,
+) -> Result<impl IntoResponse, HttpError> {
+    let (tv_channel, tv_show) = show_path(&params)?;
+    let episode = path_param(&params, "episode")?;
+    let ProgressRequest {
+        part,
+        position,
+        duration,
+        completed,
+    } = request;
+    if !(position.is_finite() && position >= 0.0 && duration.is_finite() && duration >= 0.0) {
+        return Err(ServerError::InvalidInput(format!(
+            "Invalid position {position} or duration {duration}"
+        ))
+        .into());
+    }
+    let (soap, episode) = get_episode(&state, tv_channel, tv_show, episode)
+        .await
+        .ok_or_else(|| {
+            ServerError::EpisodeNotFound(format!(
+                "Couldn't find TvEpisode with {tv_channel} > {tv_show} > {episode}"
+            ))
+        })?;
+    let progress = PartProgress {
+        position,
+        duration,
+        completed: completed.unwrap_or(duration > 0.0 && position >= duration * COMPLETED_RATIO),
+    };
+
+    let channel_id = slugify(tv_channel);
+    let show_id = url_id(&soap.url);
+    let key = history_key(&profile.id, &channel_id, &show_id, &episode.id);
+    let mut history = state
+        .history
+        .get(&key)
+        .await
+        .unwrap_or_else(|| EpisodeHistory {
+            profile: profile.id.clone(),
+            channel_id,
+            show_id,
+            show: soap.title.clone(),
+            episode_id: episode.id.clone(),
+            episode: episode.title.clone(),
+            parts: LinkedHashMap::new(),
+            completed: false,
+            updated_at: SystemTime::now(),
+        });
+    history.record(&part, progress, is_last_part(&episode, &part));
+    debug!("Recorded {part} of {key} at {position}/{duration}");
+    let response = history.to_res();
+    state.history.put(key, history).await;
+    Ok(Json(response))
+}
+
+/// The episodes which have been started but not finished, one per tv show, latest first.
+pub async fn continue_watching(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let limit = query_param(&params, "limit", DEFAULT_LIMIT)?;
+    let history = state.history.filter(|h| h.profile == profile.id).await;
+    Ok(Json(unfinished(history, limit)))
+}
+
+/// Everything watched of a tv show, latest first.
+pub async fn show_history(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+    Path(params): Path<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let (tv_channel, tv_show) = show_path(&params)?;
+    let channel_id = slugify(tv_channel);
+    // The history outlives the shows which aren't listed anymore.
+    let show_id = match state.tv_channels.get_tv_show(tv_channel, tv_show).await {
+        Some(soap) => url_id(&soap.url),
+        None => tv_show.to_owned(),
+    };
+    let mut history = state
+        .history
+        .filter(|h| h.profile == profile.id)
+        .await
+        .into_iter()
+        .filter(|h| h.channel_id == channel_id && h.show_id == show_id)
+        .collect::<Vec<_>>();
+    history.sort_by_key(|h| std::cmp::Reverse(h.updated_at));
+    Ok(Json(history.iter().map(|h| h.to_res()).collect::<Vec<_>>()))
+}
+
+fn unfinished(mut history: Vec<EpisodeHistory>, limit: usize) -> Vec<HistoryResponse> {
+    history.sort_by_key(|h| std::cmp::Reverse(h.updated_at));
+    let mut seen_shows = Vec::new();
+    history
+        .iter()
+        .filter(|h| {
+            let show = (&h.channel_id, &h.show_id);
+            let is_first = !seen_shows.contains(&show);
+            seen_shows.push(show);
+            is_first && !h.completed
+        })
+        .take(limit)
+        .map(|h| h.to_res())
+        .collect()
+}
+
+/// Whether a part is the last one of any of the providers of the episode.
+fn is_last_part(episode: &TvEpisode, part: &str) -> bool {
+    episode
+        .parts
+        .iter()
+        .any(|e| e.links.last().is_some_and(|(title, _)| title == part))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use linked_hash_map::LinkedHashMap;
+
+    use super::{unfinished, EpisodeHistory, PartProgress};
+
+    fn history(show_id: &str, episode_id: &str, age: u64) -> EpisodeHistory {
+        EpisodeHistory {
+            profile: "default".into(),
+            channel_id: "star-plus".into(),
+            show_id: show_id.into(),
+            show: show_id.into(),
+            episode_id: episode_id.into(),
+            episode: episode_id.into(),
+            parts: LinkedHashMap::new(),
+            completed: false,
+            updated_at: SystemTime::now() - Duration::from_secs(age),
+        }
+    }
+
+    #[test]
+    fn test_continue_watching() {
+        let progress = |position, completed| PartProgress {
+            position,
+            duration: 100.0,
+            completed,
+        };
+        let mut first = history("anupamaa", "e1", 30);
+        first.record("Part 1", progress(100.0, true), false);
+        first.record("Part 2", progress(40.0, false), true);
+        first.record("Part 1", progress(10.0, false), false);
+        assert_eq!(
+            first.parts.keys().collect::<Vec<_>>(),
+            vec!["Part 2", "Part 1"]
+        );
+        assert!(!first.completed);
+        first.record("Part 2", progress(100.0, true), true);
+        assert!(first.completed);
+
+        let second = history("anupamaa", "e2", 60);
+        let other = history("imlie", "e1", 90);
+        let ids = |history: Vec<EpisodeHistory>| {
+            unfinished(history, 10)
+                .into_iter()
+                .map(|h| format!("{}/{}", h.show_id, h.episode_id))
+                .collect::<Vec<_>>()
+        };
+        // The finished episode hides the older ones of the same show.
+        assert_eq!(
+            ids(vec![other.clone(), second.clone(), first]),
+            vec!["imlie/e1"]
+        );
+        assert_eq!(ids(vec![other, second]), vec!["anupamaa/e2", "imlie/e1"]);
+    }
+}
@@ -4,30 +4,22 @@ use std::time::Duration;
 
 use anyhow::anyhow;
 use cloudflare_resolver::CloudflareResolver;
-use once_cell::sync::Lazy;
 use reqwest::Client;
 use scraper::Selector;
 use url::{ParseError, Url};
 
-pub const PARALLELISM: usize = 8;
+use crate::config::Config;
 
-pub const USER_AGENT: &str =
-    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:121.0) Gecko/20100101 Firefox/121.0";
-
-static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
-    Client::builder()
-        .user_agent(USER_AGENT)
+pub fn build_http_client(config: &Config, resolver: CloudflareResolver) -> anyhow::Result<Client> {
+    let client = Client::builder()
+        .user_agent(&config.user_agent)
         .cookie_store(true)
         .use_rustls_tls()
         .danger_accept_invalid_certs(true)
-        .dns_resolver(Arc::new(CloudflareResolver::new()))
+        .dns_resolver(Arc::new(resolver))
         .connect_timeout(Duration::from_secs(60))
-        .build()
-        .unwrap()
-});
-
-pub fn http_client() -> &'static Client {
-    &HTTP_CLIENT
+        .build()?;
+    Ok(client)
 }
 
 pub fn s(selector: &str) -> Selector {
@@ -1,31 +1,110 @@
-use anyhow::Context;
-use axum::routing::{any, get};
+use std::net::{SocketAddr, TcpListener};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use axum::routing::{any, get, post, put};
 use axum::{Router, Server};
+use tokio::sync::watch;
+use tokio::time;
 use tower_http::trace::TraceLayer;
 use tracing::*;
 
-use crate::utils::set_cache_folder;
+use crate::app_state::AppState;
+use crate::scheduler::start_scheduler;
+
+pub use crate::command::{run_command, Command};
+pub use crate::config::{Config, CONFIG_FILE};
 
+mod admin;
+mod app_state;
+mod atom;
 mod channel_logo;
 mod cleanup;
+mod command;
+mod config;
+mod episode_info;
 mod error;
+mod favorites;
+mod fetcher;
 mod file;
+mod history;
 mod http_util;
 mod media;
+mod metrics;
+mod mirror;
 mod models;
+mod profiles;
+mod scheduler;
+mod search;
+mod sources;
+mod status;
+mod store;
 mod tv_channels;
 mod tv_episodes;
 mod tv_shows;
 mod utils;
 mod worker;
 
+/// How long in-flight requests (mostly `/media` streams) get to finish after a shutdown.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the background tasks get to finish once the http server has stopped.
+const RUNTIME_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Handle of a running server, it can be used to stop the server or to wait on it.
+///
+/// Dropping the handle stops the server without waiting for it.
+pub struct ServerHandle {
+    address: SocketAddr,
+    shutdown: watch::Sender<bool>,
+    thread: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
+impl ServerHandle {
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Stops accepting new connections, drains the in-flight requests and waits for the server to stop.
+    pub fn shutdown(mut self) -> anyhow::Result<()> {
+        info!("Shutting down the server at '{}'", self.address);
+        self.shutdown.send(true).ok();
+        self.join()
+    }
+
+    /// Blocks the current thread until the server stops.
+    pub fn wait(mut self) -> anyhow::Result<()> {
+        self.join()
+    }
+
+    fn join(&mut self) -> anyhow::Result<()> {
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| anyhow!("Tv show server thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.shutdown.send(true).ok();
+        }
+    }
+}
+
+/// Starts the server in a background thread and returns as soon as it is listening.
 pub fn start_server(
     cache_dir: &str,
     async_threads: usize,
     io_threads: usize,
-    port: u16,
-) -> anyhow::Result<()> {
-    set_cache_folder(cache_dir)?;
+    address: SocketAddr,
+    config: Config,
+) -> anyhow::Result<ServerHandle> {
     let rt = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(async_threads)
         .max_blocking_threads(io_threads)
@@ -34,20 +113,39 @@ pub fn start_server(
     info!(
         "Created tokio runtime with {async_threads} async-workers & {io_threads} blocking-workers"
     );
-    rt.block_on(_start_server(port))?;
-    Ok(())
-}
 
-async fn _start_server(port: u16) -> anyhow::Result<()> {
-    let address = ([0, 0, 0, 0], port).into();
-    info!("Listing for http requests at '{address}'");
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("Failed to bind {address}"))?;
+    let address = listener.local_addr()?;
+    let state = rt.block_on(AppState::init(Path::new(cache_dir), config))?;
+    let (shutdown, shutdown_receiver) = watch::channel(false);
+    let thread = thread::Builder::new()
+        .name("tv_shows_server".into())
+        .spawn(move || {
+            let result = rt.block_on(serve(state, listener, shutdown_receiver));
+            rt.shutdown_timeout(RUNTIME_SHUTDOWN_TIMEOUT);
+            info!("Tv show server has stopped");
+            result
+        })?;
+    Ok(ServerHandle {
+        address,
+        shutdown,
+        thread: Some(thread),
+    })
+}
 
-    tokio::spawn(worker::init_worker());
-    tokio::spawn(tv_shows::init_tv_shows());
-    tokio::spawn(cleanup::start_cleanup());
+async fn serve(
+    state: AppState,
+    listener: TcpListener,
+    mut shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    info!("Listing for http requests at '{}'", listener.local_addr()?);
+    // Only the server keeps the caches warm, the commands of the cli run once & exit.
+    tokio::spawn(start_scheduler(state.clone()));
 
     let app = Router::new()
         .route("/home", get(tv_channels::channel_home))
+        .route("/channels", get(tv_channels::channel_list))
         .route("/episodes/:tv_channel/:tv_show", get(tv_shows::episodes))
         .route(
             "/episode/:tv_channel/:tv_show/:episode",
@@ -59,11 +157,76 @@ async fn _start_server(port: u16) -> anyhow::Result<()> {
         )
         .route("/media", any(media::media))
         .route("/logo/:tv_channel", get(channel_logo::logo))
+        .route("/search", get(search::search))
+        .route("/search/site", get(search::site_search))
+        .route("/profiles", get(profiles::list_profiles))
+        .route(
+            "/profiles/:profile",
+            put(profiles::put_profile).delete(profiles::delete_profile),
+        )
+        .route("/profile", get(profiles::current_profile))
+        .route("/favorites", get(favorites::list_favorites))
+        .route(
+            "/favorites/:tv_channel/:tv_show",
+            put(favorites::add_favorite).delete(favorites::remove_favorite),
+        )
+        .route("/feed", get(favorites::feed))
+        .route("/feed/:tv_channel", get(atom::channel_feed))
+        .route("/feed/:tv_channel/:tv_show", get(atom::show_feed))
+        .route("/history/continue", get(history::continue_watching))
+        .route("/history/:tv_channel/:tv_show", get(history::show_history))
+        .route(
+            "/history/:tv_channel/:tv_show/:episode",
+            post(history::record_progress),
+        )
+        .route("/status", get(status::status))
+        .route("/healthz", get(status::healthz))
+        .route("/metrics", get(metrics::metrics))
+        .nest("/admin", admin::admin_routes())
         .fallback(get(file::static_assets))
-        .layer(TraceLayer::new_for_http());
+        .layer(TraceLayer::new_for_http())
+        .with_state(state);
 
-    Server::bind(&address)
+    let mut graceful_shutdown = shutdown.clone();
+    let server = Server::from_tcp(listener)?
         .serve(app.into_make_service())
-        .await
-        .context("Starting Tv show server failed")
+        .with_graceful_shutdown(async move {
+            graceful_shutdown.wait_for(|&stop| stop).await.ok();
+        });
+    tokio::select! {
+        result = server => result.context("Running Tv show server failed"),
+        _ = async {
+            shutdown.wait_for(|&stop| stop).await.ok();
+            time::sleep(DRAIN_TIMEOUT).await;
+        } => {
+            warn!("In-flight requests didn't finish in {DRAIN_TIMEOUT:?}, dropping them");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+
+    use crate::utils::TestDir;
+
+    use super::{start_server, Config};
+
+    #[test]
+    fn test_restart() {
+        let cache_dir = TestDir::new("server");
+        for _ in 0..2 {
+            let handles = ["first", "second"]
+                .map(|dir| cache_dir.join(dir))
+                .map(|dir| {
+                    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+                    start_server(dir.to_str().unwrap(), 1, 1, address, Config::default()).unwrap()
+                });
+            for handle in handles {
+                TcpStream::connect(handle.address()).unwrap();
+                handle.shutdown().unwrap();
+            }
+        }
+    }
 }
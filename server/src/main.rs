@@ -1,11 +1,14 @@
 use std::env;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 
 use mimalloc::MiMalloc;
 use structopt::StructOpt;
 use tracing_subscriber::fmt::time::OffsetTime;
 use tracing_subscriber::EnvFilter;
 
-use tv_shows_server::start_server;
+use tv_shows_server::{run_command, start_server, Command, Config, ServerHandle};
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
@@ -19,22 +22,71 @@ fn main() {
     if env::var_os(RUST_LOG).is_none() {
         env::set_var(RUST_LOG, DEFAULT_LOG_LEVEL);
     }
+    // Logs go to stderr, so that the json printed by the sub commands can be piped.
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .with_timer(OffsetTime::local_rfc_3339().unwrap())
+        .with_writer(io::stderr)
         .init();
 
-    println!(
+    eprintln!(
         "Version: {}, Log level: {:?}",
         env!("CARGO_PKG_VERSION"),
         env::var_os(RUST_LOG).unwrap_or_default()
     );
 
     let opts = Opts::from_args();
-    println!("Program arguments: {opts:?}");
+    eprintln!("Program arguments: {opts:?}");
 
-    if let Err(e) = start_server("./cache", opts.async_threads, opts.io_threads, opts.port) {
-        eprintln!("Failed to start the server: {e:?}");
+    let config = match Config::load(opts.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load the config: {e:?}");
+            return;
+        }
+    };
+    let command = match opts.command.unwrap_or(SubCommand::Serve) {
+        SubCommand::Serve => {
+            let address = SocketAddr::new(opts.bind, opts.port);
+            if let Err(e) = start_server(
+                &opts.cache_dir,
+                opts.async_threads,
+                opts.io_threads,
+                address,
+                config,
+            )
+            .and_then(ServerHandle::wait)
+            {
+                eprintln!("Failed to start the server: {e:?}");
+            }
+            return;
+        }
+        SubCommand::Channels => Command::Channels,
+        SubCommand::Episodes {
+            tv_channel,
+            tv_show,
+            load_more,
+        } => Command::Episodes {
+            tv_channel,
+            tv_show,
+            load_more,
+        },
+        SubCommand::Resolve {
+            tv_channel,
+            tv_show,
+            episode,
+        } => Command::Resolve {
+            tv_channel,
+            tv_show,
+            episode,
+        },
+    };
+    match run_command(&opts.cache_dir, config, command) {
+        Ok(json) => println!("{json}"),
+        Err(e) => {
+            eprintln!("Command failed: {e:?}");
+            std::process::exit(1);
+        }
     }
 }
 
@@ -47,4 +99,35 @@ struct Opts {
     io_threads: usize,
     #[structopt(short = "p", long = "port", default_value = "3000")]
     port: u16,
+    /// Address to listen on, e.g. `::` for IPv6 or `127.0.0.1` for loopback only
+    #[structopt(short = "l", long = "bind", default_value = "0.0.0.0")]
+    bind: IpAddr,
+    #[structopt(short = "d", long = "cache", default_value = "./cache")]
+    cache_dir: String,
+    /// Path of a `.toml` or `.json` config file
+    #[structopt(short = "c", long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<SubCommand>,
+}
+
+#[derive(StructOpt, Debug)]
+enum SubCommand {
+    /// Starts the http server (default)
+    Serve,
+    /// Prints the tv channels and their shows as json
+    Channels,
+    /// Prints the episodes of a tv show as json
+    Episodes {
+        tv_channel: String,
+        tv_show: String,
+        #[structopt(long = "load-more")]
+        load_more: bool,
+    },
+    /// Prints the playable urls of an episode's parts as json
+    Resolve {
+        tv_channel: String,
+        tv_show: String,
+        episode: String,
+    },
 }
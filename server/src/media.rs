@@ -3,7 +3,7 @@ use std::error::Error;
 
 use anyhow::anyhow;
 use axum::body::{Body, Bytes};
-use axum::extract::Query;
+use axum::extract::{Query, State};
 use axum::http::Request;
 use axum::response::{IntoResponse, Response};
 use futures::{stream, Stream};
@@ -12,8 +12,9 @@ use reqwest::header;
 use tokio::sync::mpsc::{self, Receiver};
 use tracing::*;
 
-use crate::error::HttpError;
-use crate::http_util::http_client;
+use crate::app_state::AppState;
+use crate::error::{HttpError, ServerError};
+use crate::metrics::{record_media_bytes, MeteredSend};
 
 const CHANNEL_BUFFER: usize = 32;
 
@@ -44,6 +45,7 @@ static ALLOWED_HEADERS: Lazy<HashSet<String>> = Lazy::new(|| {
 });
 
 pub async fn media(
+    State(state): State<AppState>,
     Query(params): Query<HashMap<String, String>>,
     request: Request<Body>,
 ) -> Result<impl IntoResponse, HttpError> {
@@ -59,7 +61,7 @@ pub async fn media(
     let referer = params.get("referer");
     info!("{}: {} [Referer:{:?}]", request.method(), url, referer);
 
-    let mut req = http_client().request(request.method().clone(), url);
+    let mut req = state.http_client.request(request.method().clone(), url);
     if let Some(referer) = referer {
         req = req.header(header::REFERER, referer);
     }
@@ -69,9 +71,9 @@ pub async fn media(
         }
     }
     let res = req
-        .send()
+        .metered_send()
         .await
-        .map_err(|e| anyhow!("Failed to fetch {url}, {e:?}"))?;
+        .map_err(|e| ServerError::Upstream(format!("Failed to fetch {url}, {e:?}")))?;
     debug!("Status: {}, header: {:?}", res.status(), res.headers());
     Ok(response_to_body(res).await?)
 }
@@ -93,6 +95,7 @@ async fn response_to_body(mut response: reqwest::Response) -> anyhow::Result<Res
     let (sender, receiver) = mpsc::channel(CHANNEL_BUFFER);
     tokio::spawn(async move {
         while let Ok(Some(bytes)) = response.chunk().await {
+            record_media_bytes(bytes.len());
             if sender.send(bytes).await.is_err() {
                 break;
             }
@@ -0,0 +1,172 @@
+use std::future::Future;
+use std::time::Instant;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use reqwest::{RequestBuilder, Response};
+
+use crate::app_state::AppState;
+use crate::error::HttpError;
+use crate::models::VideoProvider;
+
+const PREFIX: &str = "tv_shows";
+
+static REGISTRY: Lazy<Registry> = Lazy::new(new_registry);
+
+static UPSTREAM_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "upstream_requests_total",
+            "Requests sent to the upstream sites",
+        ),
+        &["host", "status"],
+    ))
+});
+
+static SCRAPE_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "scrape_duration_seconds",
+            "Time spent in the scraping steps",
+        )
+        .buckets(vec![0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]),
+        &["step", "provider"],
+    ))
+});
+
+static PROVIDER_RESOLUTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "provider_resolutions_total",
+            "Attempts to resolve the video url of an episode part",
+        ),
+        &["provider", "result"],
+    ))
+});
+
+static MEDIA_BYTES: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new(
+        "media_bytes_total",
+        "Bytes proxied through /media",
+    ))
+});
+
+static WORKER_TIMEOUTS: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new(
+        "worker_timeouts_total",
+        "Worker tasks which didn't finish in time",
+    ))
+});
+
+fn new_registry() -> Registry {
+    Registry::new_custom(Some(PREFIX.into()), None).expect("Invalid metric prefix")
+}
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(metric: prometheus::Result<T>) -> T {
+    let metric = metric.expect("Invalid metric definition");
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("Metric is registered twice");
+    metric
+}
+
+/// Sends the request, counting it by the upstream host and the response status.
+pub trait MeteredSend {
+    fn metered_send(self) -> impl Future<Output = reqwest::Result<Response>> + Send;
+}
+
+impl MeteredSend for RequestBuilder {
+    async fn metered_send(self) -> reqwest::Result<Response> {
+        let (client, request) = self.build_split();
+        let request = request?;
+        let host = request.url().host_str().unwrap_or_default().to_owned();
+        let response = client.execute(request).await;
+        let status = match &response {
+            Ok(response) => response.status().as_u16().to_string(),
+            Err(_) => "error".to_owned(),
+        };
+        UPSTREAM_REQUESTS.with_label_values(&[&host, &status]).inc();
+        response
+    }
+}
+
+/// Times `future` as one run of the scraping `step`.
+pub async fn time_step<T>(
+    step: &str,
+    provider: Option<VideoProvider>,
+    future: impl Future<Output = T>,
+) -> T {
+    let provider = provider.map(|p| format!("{p:?}")).unwrap_or_default();
+    let start = Instant::now();
+    let result = future.await;
+    SCRAPE_DURATION
+        .with_label_values(&[step, &provider])
+        .observe(start.elapsed().as_secs_f64());
+    result
+}
+
+pub fn record_resolution(provider: VideoProvider, success: bool) {
+    let result = if success { "success" } else { "failure" };
+    PROVIDER_RESOLUTIONS
+        .with_label_values(&[&format!("{provider:?}"), result])
+        .inc();
+}
+
+pub fn record_media_bytes(bytes: usize) {
+    MEDIA_BYTES.inc_by(bytes as u64);
+}
+
+pub fn record_worker_timeout() {
+    WORKER_TIMEOUTS.inc();
+}
+
+pub async fn metrics(State(state): State<AppState>) -> Result<impl IntoResponse, HttpError> {
+    let (content_type, buffer) = encode_metrics(&state)?;
+    Ok(([(header::CONTENT_TYPE, content_type)], buffer))
+}
+
+fn encode_metrics(state: &AppState) -> anyhow::Result<(String, Vec<u8>)> {
+    // The dns resolver keeps its own counts, they're copied into a registry of their own.
+    let dns_registry = new_registry();
+    let dns_lookups = IntCounter::new("dns_lookups_total", "Queries sent to the DoH server")?;
+    dns_lookups.inc_by(state.dns_resolver.lookups());
+    dns_registry.register(Box::new(dns_lookups))?;
+    let dns_cache_hits = IntCounter::new("dns_cache_hits_total", "Dns answers from the cache")?;
+    dns_cache_hits.inc_by(state.dns_resolver.cache_hits());
+    dns_registry.register(Box::new(dns_cache_hits))?;
+
+    // Touch the metrics, so that they're listed even before their first sample.
+    Lazy::force(&UPSTREAM_REQUESTS);
+    Lazy::force(&SCRAPE_DURATION);
+    Lazy::force(&PROVIDER_RESOLUTIONS);
+    Lazy::force(&MEDIA_BYTES);
+    Lazy::force(&WORKER_TIMEOUTS);
+
+    let mut families = REGISTRY.gather();
+    families.extend(dns_registry.gather());
+    let mut buffer = Vec::new();
+    let encoder = TextEncoder::new();
+    encoder.encode(&families, &mut buffer)?;
+    Ok((encoder.format_type().to_owned(), buffer))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{record_media_bytes, REGISTRY};
+
+    #[test]
+    fn test_media_bytes() {
+        record_media_bytes(42);
+        let families = REGISTRY.gather();
+        let media_bytes = families
+            .iter()
+            .find(|family| family.get_name() == "tv_shows_media_bytes_total")
+            .unwrap();
+        assert!(media_bytes.get_metric()[0].get_counter().get_value() >= 42.0);
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+use tokio::fs;
+use tracing::*;
+
+use crate::config::Config;
+use crate::http_util::find_host;
+use crate::utils::{encode_uri_component, MIRROR_FILE};
+
+/// Tracks which of the domains of the source site is working at the moment.
+///
+/// The site moves to a new domain every now and then, the last working one is saved in the cache folder.
+pub struct Mirror {
+    file: PathBuf,
+    active: RwLock<String>,
+    known: RwLock<Vec<String>>,
+    changed: AtomicBool,
+}
+
+impl Mirror {
+    pub async fn load(cache_folder: &Path, config: &Config) -> Mirror {
+        let file = cache_folder.join(MIRROR_FILE);
+        let mut known = Vec::new();
+        for url in [&config.desi_tv].into_iter().chain(&config.mirrors) {
+            match find_host(url) {
+                Ok(host) if !known.contains(&host) => known.push(host),
+                Ok(_) => {}
+                Err(e) => warn!("Ignoring invalid mirror {url}: {e}"),
+            }
+        }
+        let saved = fs::read_to_string(&file)
+            .await
+            .ok()
+            .and_then(|content| find_host(content.trim()).ok());
+        let active = match saved {
+            Some(host) => {
+                info!("Using the last working mirror: {host}");
+                if !known.contains(&host) {
+                    known.push(host.clone());
+                }
+                host
+            }
+            None => known.first().cloned().unwrap_or_default(),
+        };
+        Mirror {
+            file,
+            active: RwLock::new(active),
+            known: RwLock::new(known),
+            // The cached urls may predate the saved mirror, so they're checked once at least.
+            changed: AtomicBool::new(true),
+        }
+    }
+
+    pub fn active(&self) -> String {
+        self.active.read().unwrap().clone()
+    }
+
+    /// Home pages to try in order, starting with the active one.
+    pub fn candidates(&self) -> Vec<String> {
+        let active = self.active();
+        let mut candidates = vec![active.clone()];
+        candidates.extend(
+            self.known
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|&host| host != &active)
+                .cloned(),
+        );
+        candidates
+    }
+
+    /// Makes the host of `url` the active mirror, if it's not already.
+    pub async fn set_active(&self, url: &str) -> anyhow::Result<()> {
+        let host = find_host(url)?;
+        if *self.active.read().unwrap() == host {
+            return Ok(());
+        }
+        warn!("Source site has moved to {host}");
+        {
+            let mut known = self.known.write().unwrap();
+            if !known.contains(&host) {
+                known.push(host.clone());
+            }
+        }
+        *self.active.write().unwrap() = host.clone();
+        self.changed.store(true, Ordering::Relaxed);
+        fs::write(&self.file, host).await?;
+        Ok(())
+    }
+
+    /// Switches to the new domain if a request to the active mirror was redirected to one.
+    pub async fn follow_redirect(&self, requested: &str, landed: &str) -> anyhow::Result<()> {
+        if find_host(requested)? == self.active() && find_host(landed)? != self.active() {
+            self.set_active(landed).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns true once after every switch of the active mirror.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+
+    /// Points a url (or a `/media` url wrapping one) at any of the old mirrors to the active one.
+    pub fn rebase(&self, url: &str) -> String {
+        let active = self.active();
+        let mut url = url.to_owned();
+        for host in self.known.read().unwrap().iter().filter(|&h| h != &active) {
+            if url.contains(host.as_str()) {
+                url = url.replace(host.as_str(), &active);
+            }
+            let encoded = encode_uri_component(host);
+            if url.contains(&encoded) {
+                url = url.replace(&encoded, &encode_uri_component(&active));
+            }
+        }
+        url
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::utils::TestDir;
+
+    use super::Mirror;
+
+    #[tokio::test]
+    async fn test_rebase() {
+        let cache_dir = TestDir::new("mirror");
+        let config = Config {
+            desi_tv: "https://www.yodesitv.info".into(),
+            mirrors: vec!["https://www.desitellybox.me".into()],
+            ..Config::default()
+        };
+        let mirror = Mirror::load(&cache_dir, &config).await;
+        assert!(mirror.take_changed());
+        assert!(!mirror.take_changed());
+
+        mirror
+            .follow_redirect(
+                "https://www.yodesitv.info/star-plus/",
+                "https://www.desitellybox.me/star-plus/",
+            )
+            .await
+            .unwrap();
+        assert_eq!(mirror.active(), "https://www.desitellybox.me");
+        assert!(mirror.take_changed());
+        assert_eq!(
+            mirror.rebase("https://www.yodesitv.info/anupamaa/"),
+            "https://www.desitellybox.me/anupamaa/"
+        );
+        assert_eq!(
+            mirror.rebase("/media?url=https%3A%2F%2Fwww.yodesitv.info%2Ficon.jpg"),
+            "/media?url=https%3A%2F%2Fwww.desitellybox.me%2Ficon.jpg"
+        );
+
+        let mirror = Mirror::load(&cache_dir, &config).await;
+        assert_eq!(mirror.active(), "https://www.desitellybox.me");
+    }
+}
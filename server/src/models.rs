@@ -1,3 +1,5 @@
+use std::time::SystemTime;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -7,11 +9,55 @@ pub struct TvShow {
     pub icon: String,
 }
 
+/// A tv show whose episodes have been asked for, it's kept warm by the scheduler.
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OpenedShow {
+    /// Title or id of the channel the show was opened from.
+    pub channel: String,
+    pub tv_show: TvShow,
+    pub opened_at: SystemTime,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct TvShowEpisodes {
-    pub episodes: Vec<(String, Vec<Episode>)>,
+    pub episodes: Vec<TvEpisode>,
+    /// Last page which has been loaded.
     pub cur_page: usize,
     pub last_page: usize,
+    /// Episode links on a full page, 0 until it's known.
+    #[serde(default)]
+    pub page_size: usize,
+    /// Episode links from the top of the first page which have been loaded, including the
+    /// ones without any video.
+    #[serde(default)]
+    pub loaded_links: usize,
+    /// Episode links of the tv show, known once the last page has been loaded.
+    #[serde(default)]
+    pub total_links: Option<usize>,
+    /// When the first page was last downloaded, the later pages don't change as often.
+    #[serde(default)]
+    pub refreshed_at: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TvEpisode {
+    /// Stable id, see [`crate::utils::url_id`].
+    pub id: String,
+    pub title: String,
+    pub parts: Vec<Episode>,
+}
+
+/// What can be told about an episode from its title and its parts.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EpisodeInfo {
+    pub id: String,
+    pub title: String,
+    pub show: String,
+    /// Air date as `YYYY-MM-DD`.
+    pub date: Option<String>,
+    pub number: Option<u32>,
+    pub part_count: usize,
+    pub providers: Vec<VideoProvider>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -20,7 +66,7 @@ pub struct Episode {
     pub links: Vec<(String, String)>,
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum VideoProvider {
     TVLogy,
     FlashPlayer,
@@ -0,0 +1,193 @@
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Path, State};
+use axum::http::request::Parts;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::*;
+
+use crate::app_state::AppState;
+use crate::error::{HttpError, ServerError};
+use crate::store::{KeyedStateWrapper, Store, PROFILES};
+use crate::utils::slugify;
+
+/// The profiles, by their ids.
+pub type ProfilesStateWrapper = KeyedStateWrapper<Profile>;
+
+/// Profile of the requests which don't name one, it always exists.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Header naming the profile of a request, the `profile` query param works too.
+pub const PROFILE_HEADER: &str = "x-profile";
+
+/// Profile of the entries saved before there were profiles.
+pub fn default_profile() -> String {
+    DEFAULT_PROFILE.to_owned()
+}
+
+/// Member of the household, their favorites & watch history are kept apart from the others'.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    /// Ids of the channels left out of the home page & the search results.
+    #[serde(default)]
+    pub hidden_channels: Vec<String>,
+    #[serde(default)]
+    pub quality: Quality,
+}
+
+/// Preferred video quality, the best variant which isn't taller is picked.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quality {
+    #[default]
+    Best,
+    High,
+    Medium,
+    Low,
+}
+
+impl Quality {
+    pub fn max_height(&self) -> Option<u32> {
+        match self {
+            Quality::Best => None,
+            Quality::High => Some(1080),
+            Quality::Medium => Some(720),
+            Quality::Low => Some(480),
+        }
+    }
+}
+
+impl Profile {
+    fn new(id: &str) -> Profile {
+        Profile {
+            id: id.to_owned(),
+            name: id.to_owned(),
+            hidden_channels: Vec::new(),
+            quality: Quality::Best,
+        }
+    }
+
+    pub fn is_hidden(&self, channel: &str) -> bool {
+        self.hidden_channels.contains(&slugify(channel))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileRequest {
+    name: Option<String>,
+    hidden_channels: Option<Vec<String>>,
+    quality: Option<Quality>,
+}
+
+/// Profile of the request, from the `x-profile` header or the `profile` query param.
+pub struct CurrentProfile(pub Profile);
+
+#[async_trait]
+impl FromRequestParts<AppState> for CurrentProfile {
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let from_header = parts
+            .headers
+            .get(PROFILE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let from_query = || {
+            let query = parts.uri.query()?;
+            form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "profile")
+                .map(|(_, value)| value.into_owned())
+        };
+        let id = from_header
+            .or_else(from_query)
+            .map(|id| slugify(&id))
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_owned());
+        let profile = state
+            .profiles
+            .get(&id)
+            .await
+            .ok_or_else(|| ServerError::InvalidInput(format!("Unknown profile '{id}'")))?;
+        Ok(CurrentProfile(profile))
+    }
+}
+
+/// Loads the profiles, the default one is created on the first start.
+pub async fn load_profiles(store: Store) -> ProfilesStateWrapper {
+    let profiles = ProfilesStateWrapper::load(store, PROFILES, |p: &Profile| p.id.clone()).await;
+    if profiles.get(DEFAULT_PROFILE).await.is_none() {
+        let profile = Profile::new(DEFAULT_PROFILE);
+        profiles.put(profile.id.clone(), profile).await;
+    }
+    profiles
+}
+
+pub async fn list_profiles(State(state): State<AppState>) -> Result<impl IntoResponse, HttpError> {
+    Ok(Json(state.profiles.all().await))
+}
+
+pub async fn current_profile(
+    CurrentProfile(profile): CurrentProfile,
+) -> Result<impl IntoResponse, HttpError> {
+    Ok(Json(profile))
+}
+
+/// Creates a profile, or changes the given settings of an existing one.
+pub async fn put_profile(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<ProfileRequest>,
+) -> Result<impl IntoResponse, HttpError> {
+    let id = slugify(&id);
+    if id.is_empty() {
+        return Err(ServerError::InvalidInput("Profile id has no letters or digits".into()).into());
+    }
+    let mut profile = state
+        .profiles
+        .get(&id)
+        .await
+        .unwrap_or_else(|| Profile::new(&id));
+    let ProfileRequest {
+        name,
+        hidden_channels,
+        quality,
+    } = request;
+    if let Some(name) = name {
+        profile.name = name;
+    }
+    if let Some(hidden_channels) = hidden_channels {
+        profile.hidden_channels = hidden_channels.iter().map(|c| slugify(c)).collect();
+    }
+    if let Some(quality) = quality {
+        profile.quality = quality;
+    }
+    info!("Saving profile {profile:?}");
+    state.profiles.put(id, profile.clone()).await;
+    Ok(Json(profile))
+}
+
+/// Deletes a profile along with its favorites & watch history.
+pub async fn delete_profile(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let id = slugify(&id);
+    if id == DEFAULT_PROFILE {
+        return Err(
+            ServerError::InvalidInput("The default profile can't be deleted".into()).into(),
+        );
+    }
+    let removed = state.profiles.remove(&id).await;
+    if removed {
+        info!("Deleting the favorites & watch history of profile {id}");
+        state.favorites.remove_where(|f| f.profile == id).await;
+        state.history.remove_where(|h| h.profile == id).await;
+    }
+    Ok(Json(json!({ "removed": removed })))
+}
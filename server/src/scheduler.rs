@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use futures::{stream, StreamExt};
+use tokio::time;
+use tracing::*;
+
+use crate::app_state::AppState;
+use crate::favorites::check_favorites;
+use crate::models::OpenedShow;
+use crate::profiles::Quality;
+use crate::tv_channels::prefetch_tv_channels;
+use crate::tv_episodes::resolve_episode;
+use crate::tv_shows::{cached_episodes, tv_show_episodes};
+use crate::utils::url_id;
+
+/// Shortest nap of the scheduler, so that a failing download isn't retried in a loop.
+const MIN_SLEEP: Duration = Duration::from_secs(5 * 60);
+
+/// Downloads the channels before they expire, keeps the recently opened tv shows warm and
+/// checks the favorites for new episodes, so that the first request of the day doesn't wait
+/// on the source site.
+pub async fn start_scheduler(state: AppState) -> ! {
+    let mut next_warm = Instant::now() + MIN_SLEEP;
+    let mut next_check = Instant::now() + MIN_SLEEP;
+    loop {
+        if channels_due(&state)
+            .await
+            .is_some_and(|due| due <= SystemTime::now())
+        {
+            info!("TV channels are about to expire, downloading them again");
+            prefetch_tv_channels(&state).await;
+        }
+        if next_warm <= Instant::now() {
+            warm_tv_shows(&state).await;
+            next_warm = Instant::now() + state.config.warm_interval();
+        }
+        if next_check <= Instant::now() {
+            check_favorites(&state).await;
+            next_check = Instant::now() + state.config.feed_interval();
+        }
+
+        let mut sleep_dur = next_warm
+            .min(next_check)
+            .saturating_duration_since(Instant::now());
+        if let Some(due) = channels_due(&state).await {
+            let until_due = due.duration_since(SystemTime::now()).unwrap_or_default();
+            sleep_dur = sleep_dur.min(until_due);
+        }
+        let sleep_dur = sleep_dur.max(MIN_SLEEP);
+        debug!("Scheduler sleeping for {sleep_dur:?}");
+        time::sleep(sleep_dur).await;
+    }
+}
+
+/// When the channels should be downloaded again, there's nothing to keep fresh until
+/// they've been asked for once.
+async fn channels_due(state: &AppState) -> Option<SystemTime> {
+    if state.tv_channels.is_empty().await {
+        return None;
+    }
+    let expires_at = state.tv_channels.expires_at().await;
+    Some(expires_at - state.config.channels_prefetch())
+}
+
+async fn warm_tv_shows(state: &AppState) {
+    let parallelism = state.config.warm_parallelism;
+    if parallelism == 0 {
+        return;
+    }
+    let tv_shows = state
+        .tv_shows
+        .recently_opened(state.config.warm_window())
+        .await;
+    if tv_shows.is_empty() {
+        return;
+    }
+    let start = Instant::now();
+    info!("Warming {} recently opened tv shows", tv_shows.len());
+    stream::iter(tv_shows)
+        .for_each_concurrent(parallelism, |opened| async move {
+            if let Err(e) = warm_tv_show(state, &opened).await {
+                warn!("Failed to warm '{}': {e:?}", opened.tv_show.title);
+            }
+        })
+        .await;
+    info!("Time taken to warm the tv shows: {:?}", start.elapsed());
+}
+
+/// Checks the first page of a tv show for new episodes, and resolves the parts of the newest one.
+async fn warm_tv_show(state: &AppState, opened: &OpenedShow) -> anyhow::Result<()> {
+    let tv_channel = &opened.channel;
+    let tv_show = url_id(&opened.tv_show.url);
+    tv_show_episodes(state, tv_channel, &tv_show, false).await?;
+    let episodes = cached_episodes(state, tv_channel, &tv_show).await;
+    if let Some(episode) = episodes.as_ref().and_then(|episodes| episodes.first()) {
+        resolve_episode(state, tv_channel, &tv_show, &episode.id, Quality::Best).await?;
+    }
+    Ok(())
+}
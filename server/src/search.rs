@@ -0,0 +1,259 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use axum::extract::{Query, State};
+use axum::response::IntoResponse;
+use axum::Json;
+use linked_hash_map::LinkedHashMap;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use crate::app_state::AppState;
+use crate::error::{query_param, HttpError, ServerError};
+use crate::metrics::time_step;
+use crate::models::TvShow;
+use crate::profiles::CurrentProfile;
+use crate::tv_channels::tv_channels;
+use crate::utils::{encode_uri_component, fix_title, slugify, url_id};
+
+const DEFAULT_LIMIT: usize = 20;
+
+/// Channel of the shows found by the site search, their episodes are opened under it.
+pub const SITE_SEARCH_CHANNEL: &str = "Search";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SearchResult {
+    pub channel_id: String,
+    pub channel: String,
+    pub id: String,
+    pub title: String,
+    pub icon: String,
+}
+
+impl SearchResult {
+    fn new(channel: &str, tv_show: &TvShow) -> SearchResult {
+        SearchResult {
+            channel_id: slugify(channel),
+            channel: channel.to_owned(),
+            id: url_id(&tv_show.url),
+            title: tv_show.title.clone(),
+            icon: tv_show.icon.clone(),
+        }
+    }
+}
+
+struct IndexEntry {
+    result: SearchResult,
+    words: Vec<String>,
+}
+
+/// In-memory index of the show titles of all the channels, for typo tolerant lookups.
+#[derive(Default)]
+pub struct SearchIndex {
+    entries: Vec<IndexEntry>,
+}
+
+impl SearchIndex {
+    pub fn build(channels: &LinkedHashMap<String, Vec<TvShow>>) -> SearchIndex {
+        let entries = channels
+            .iter()
+            .flat_map(|(channel, tv_shows)| {
+                tv_shows.iter().map(move |tv_show| IndexEntry {
+                    result: SearchResult::new(channel, tv_show),
+                    words: words(&tv_show.title),
+                })
+            })
+            .collect();
+        SearchIndex { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Shows whose titles match every word of the query, the best matches first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        let query = words(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut matches = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let mut score = 0.0;
+                for word in &query {
+                    let best = entry
+                        .words
+                        .iter()
+                        .map(|title_word| word_score(word, title_word))
+                        .fold(0.0, f64::max);
+                    if best == 0.0 {
+                        return None;
+                    }
+                    score += best;
+                }
+                // Between two equally good matches, the shorter title is the closer one.
+                let score = score / query.len() as f64 - entry.words.len() as f64 * 0.001;
+                Some((score, &entry.result))
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|(s1, r1), (s2, r2)| {
+            s2.partial_cmp(s1)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| r1.title.cmp(&r2.title))
+        });
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, result)| result.clone())
+            .collect()
+    }
+}
+
+pub async fn search(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let query = search_query(&params)?;
+    let limit = query_param(&params, "limit", DEFAULT_LIMIT)?;
+    // Makes sure the channels (and so the index) are loaded and haven't expired.
+    tv_channels(&state).await?;
+    let results = state
+        .tv_channels
+        .search(query, usize::MAX)
+        .into_iter()
+        .filter(|result| !profile.is_hidden(&result.channel_id))
+        .take(limit)
+        .collect::<Vec<_>>();
+    info!("Search '{query}' matched {} tv shows", results.len());
+    Ok(Json(results))
+}
+
+/// Searches the source site itself, which finds the shows that aren't listed on any channel page.
+pub async fn site_search(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let query = search_query(&params)?.to_owned();
+    let source = state.source.clone();
+    let mut tv_shows = state
+        .worker
+        .run(async move { time_step("search_shows", None, source.search_shows(&query)).await })
+        .await?;
+    state.follow_mirror().await?;
+    for tv_show in &mut tv_shows {
+        tv_show.icon = format!("/media?url={}", encode_uri_component(&tv_show.icon));
+    }
+    state.tv_channels.add_found_shows(&tv_shows).await?;
+    info!("Site search found {} tv shows", tv_shows.len());
+    let results = tv_shows
+        .iter()
+        .map(|tv_show| SearchResult::new(SITE_SEARCH_CHANNEL, tv_show))
+        .collect::<Vec<_>>();
+    Ok(Json(results))
+}
+
+fn search_query(params: &HashMap<String, String>) -> Result<&str, ServerError> {
+    params
+        .get("q")
+        .map(|q| q.trim())
+        .filter(|q| !q.is_empty())
+        .ok_or_else(|| ServerError::InvalidInput("No search query in 'q'".into()))
+}
+
+fn words(text: &str) -> Vec<String> {
+    fix_title(text)
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn word_score(query: &str, word: &str) -> f64 {
+    if query == word {
+        return 1.0;
+    }
+    if word.starts_with(query) {
+        return 0.8;
+    }
+    let query_len = query.chars().count();
+    let allowed_typos = match query_len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    if allowed_typos == 0 {
+        return 0.0;
+    }
+    // A typo in a word which is still being typed.
+    let prefix = word.chars().take(query_len).collect::<String>();
+    let distance = edit_distance(query, word).min(edit_distance(query, &prefix) + 1);
+    if distance <= allowed_typos {
+        0.6 - 0.1 * distance as f64
+    } else {
+        0.0
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use linked_hash_map::LinkedHashMap;
+
+    use crate::models::TvShow;
+
+    use super::SearchIndex;
+
+    fn tv_show(title: &str) -> TvShow {
+        TvShow {
+            title: title.into(),
+            url: format!("https://example.com/{title}/"),
+            icon: "icon".into(),
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let mut channels = LinkedHashMap::new();
+        channels.insert(
+            "Star Plus".to_owned(),
+            vec![tv_show("Anupamaa"), tv_show("Yeh Rishta Kya Kehlata Hai")],
+        );
+        channels.insert(
+            "Colors".to_owned(),
+            vec![tv_show("Naagin 6"), tv_show("Bigg Boss 17")],
+        );
+        let index = SearchIndex::build(&channels);
+
+        let titles = |query| {
+            index
+                .search(query, 10)
+                .into_iter()
+                .map(|result| result.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles("anupama"), vec!["Anupamaa"]);
+        assert_eq!(titles("Anupmaa Watch Online"), vec!["Anupamaa"]);
+        assert_eq!(titles("rishta kehlta"), vec!["Yeh Rishta Kya Kehlata Hai"]);
+        assert_eq!(titles("bigg"), vec!["Bigg Boss 17"]);
+        assert!(titles("cricket").is_empty());
+        assert!(titles("anupamaa cricket").is_empty());
+        assert_eq!(index.search("naagin", 10)[0].channel, "Colors");
+    }
+}
@@ -0,0 +1,493 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use reqwest::header;
+use scraper::{ElementRef, Html};
+use tracing::*;
+
+use crate::config::Config;
+use crate::error::ServerError;
+use crate::fetcher::Fetcher;
+use crate::http_util::{find_host, normalize_url, s};
+use crate::mirror::Mirror;
+use crate::models::{Episode, TvShow, VideoProvider};
+use crate::sources::selectors::SelectorProfile;
+use crate::sources::{ContentSource, EpisodePage};
+use crate::utils::{encode_uri_component, fix_title};
+
+/// The desi tv site configured by `desi_tv`, the markup of which the server was first written for.
+pub struct DesiTvSource {
+    fetcher: Fetcher,
+    mirror: Arc<Mirror>,
+    no_of_channel_rows: usize,
+    no_icon: String,
+    selectors: RwLock<Arc<SelectorProfile>>,
+    selector_profile: Option<PathBuf>,
+}
+
+impl DesiTvSource {
+    pub async fn new(fetcher: Fetcher, config: &Config, mirror: Arc<Mirror>) -> Self {
+        let selectors = SelectorProfile::load(config.selector_profile.as_deref())
+            .await
+            .unwrap_or_else(|e| {
+                error!("Failed to load the selector profile, using the defaults: {e:?}");
+                SelectorProfile::default()
+            });
+        DesiTvSource {
+            fetcher,
+            mirror,
+            no_of_channel_rows: config.no_of_channel_rows,
+            no_icon: config.no_icon.clone(),
+            selectors: RwLock::new(Arc::new(selectors)),
+            selector_profile: config.selector_profile.clone(),
+        }
+    }
+
+    fn selectors(&self) -> Arc<SelectorProfile> {
+        self.selectors.read().unwrap().clone()
+    }
+
+    async fn reload_selectors(&self) -> anyhow::Result<u32> {
+        // A broken profile leaves the current one in place.
+        let selectors = SelectorProfile::load(self.selector_profile.as_deref()).await?;
+        let version = selectors.version;
+        *self.selectors.write().unwrap() = Arc::new(selectors);
+        Ok(version)
+    }
+
+    async fn fetch(&self, url: &str, referer: &str) -> anyhow::Result<String> {
+        let (html, landed_url) = self.fetch_page(url, referer).await?;
+        self.mirror.follow_redirect(url, &landed_url).await?;
+        Ok(html)
+    }
+
+    /// Returns the html and the url the request has landed on after the redirects.
+    async fn fetch_page(&self, url: &str, referer: &str) -> anyhow::Result<(String, String)> {
+        let page = self
+            .fetcher
+            .get(url)
+            .header(header::REFERER, referer)
+            .success()
+            .await?;
+        Ok((page.text, page.landed_url))
+    }
+
+    async fn load_channels(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let mut last_error = None;
+        for home_url in self.mirror.candidates() {
+            match self.load_channels_from(&home_url).await {
+                Ok(tv_channels) => return Ok(tv_channels),
+                Err(e) => {
+                    warn!("Failed to load TV channels from {home_url}: {e:?}");
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow!("No domain is configured for the source site")))
+    }
+
+    async fn load_channels_from(&self, desi_tv: &str) -> anyhow::Result<Vec<(String, String)>> {
+        info!("Loading TV channels from {desi_tv}");
+        let (html, landed_url) = self.fetch_page(desi_tv, desi_tv).await?;
+        let desi_tv = &find_host(&landed_url)?;
+        let selectors = self.selectors();
+        let mut tv_channels = parse_channels(&html, desi_tv, self.no_of_channel_rows, &selectors);
+        if tv_channels.is_empty() {
+            return Err(
+                ServerError::Parse(format!("Didn't find any tv channel in {desi_tv}")).into(),
+            );
+        }
+        if tv_channels
+            .last()
+            .map(|(title, _)| title.contains("View All"))
+            .unwrap_or(false)
+        {
+            let (_, link) = tv_channels.pop().unwrap();
+            let html = self.fetch(&link, desi_tv).await?;
+            tv_channels.extend(parse_web_series(&html, &link, &selectors));
+        }
+        // Either a mirror or the domain the home page has redirected to.
+        self.mirror.set_active(desi_tv).await?;
+        Ok(tv_channels)
+    }
+
+    async fn load_shows(&self, channel_url: &str) -> anyhow::Result<Vec<TvShow>> {
+        info!("Downloading {channel_url}");
+        let html = self.fetch(channel_url, &self.mirror.active()).await?;
+        let no_icon = self.mirror.rebase(&self.no_icon);
+        Ok(parse_tv_shows(
+            &html,
+            channel_url,
+            &no_icon,
+            &self.selectors(),
+        ))
+    }
+
+    async fn load_episode_page(&self, show_url: &str, page: usize) -> anyhow::Result<EpisodePage> {
+        let url = if page <= 1 {
+            show_url.to_owned()
+        } else {
+            format!("{show_url}page/{page}/")
+        };
+        let html = self.fetch(&url, &find_host(&url)?).await?;
+        let (links, cur_page, last_page) = find_episode_links(&html, &url, &self.selectors());
+        Ok(EpisodePage {
+            url,
+            links,
+            cur_page,
+            last_page,
+        })
+    }
+
+    async fn load_episode_links(
+        &self,
+        episode_url: &str,
+        referer: &str,
+    ) -> anyhow::Result<(String, Vec<Episode>)> {
+        let html = self.fetch(episode_url, referer).await?;
+        Ok(find_episode_video_links(&html, &self.selectors()))
+    }
+
+    async fn search(&self, query: &str) -> anyhow::Result<Vec<TvShow>> {
+        let home = self.mirror.active();
+        let url = format!("{home}/?s={}", encode_uri_component(query));
+        info!("Searching {url}");
+        let html = self.fetch(&url, &home).await?;
+        let no_icon = self.mirror.rebase(&self.no_icon);
+        Ok(parse_search_results(
+            &html,
+            &home,
+            &no_icon,
+            &self.selectors(),
+        ))
+    }
+}
+
+impl ContentSource for DesiTvSource {
+    fn name(&self) -> &str {
+        "desi_tv"
+    }
+
+    fn list_channels(&self) -> BoxFuture<'_, anyhow::Result<Vec<(String, String)>>> {
+        self.load_channels().boxed()
+    }
+
+    fn list_shows<'a>(
+        &'a self,
+        channel_url: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<TvShow>>> {
+        self.load_shows(channel_url).boxed()
+    }
+
+    fn list_episodes<'a>(
+        &'a self,
+        show_url: &'a str,
+        page: usize,
+    ) -> BoxFuture<'a, anyhow::Result<EpisodePage>> {
+        self.load_episode_page(show_url, page).boxed()
+    }
+
+    fn list_episode_links<'a>(
+        &'a self,
+        episode_url: &'a str,
+        referer: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<(String, Vec<Episode>)>> {
+        self.load_episode_links(episode_url, referer).boxed()
+    }
+
+    fn search_shows<'a>(&'a self, query: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<TvShow>>> {
+        self.search(query).boxed()
+    }
+
+    fn reload(&self) -> BoxFuture<'_, anyhow::Result<String>> {
+        async {
+            let version = self.reload_selectors().await?;
+            Ok(format!("selector profile v{version}"))
+        }
+        .boxed()
+    }
+}
+
+fn parse_channels(
+    html: &str,
+    host: &str,
+    no_of_channel_rows: usize,
+    sel: &SelectorProfile,
+) -> Vec<(String, String)> {
+    fn find_main_channels(
+        a: ElementRef,
+        host: &str,
+        sel: &SelectorProfile,
+    ) -> Option<(String, String)> {
+        let mut title = None;
+        let link = normalize_url(a.value().attr("href")?, host).ok()?;
+        let mut prev = a.parent()?.prev_sibling();
+        while let Some(p) = prev {
+            let p_class = p
+                .value()
+                .as_element()
+                .and_then(|p_ele| p_ele.attr("class"))
+                .unwrap_or("");
+            if p_class.contains(&sel.channel_title_class) {
+                let p = ElementRef::wrap(p)?;
+                let html = p.select(&s(&sel.channel_title)).next()?.inner_html();
+                title = Some(fix_title(html));
+                break;
+            }
+            prev = p.prev_sibling();
+        }
+        Some((title?, link.into_owned()))
+    }
+
+    fn find_extra_channels(
+        div: &ElementRef,
+        host: &str,
+        sel: &SelectorProfile,
+    ) -> Option<(String, String)> {
+        let a = div.select(&s(&sel.extra_channel)).next()?;
+        let link = normalize_url(a.value().attr("href")?, host).ok()?;
+        Some((a.inner_html(), link.into_owned()))
+    }
+
+    let mut tv_channels = Vec::new();
+    let doc = Html::parse_document(html);
+    tv_channels.extend(
+        doc.select(&s(&sel.main_channel))
+            .filter_map(|a| find_main_channels(a, host, sel)),
+    );
+    if tv_channels.len() > no_of_channel_rows {
+        for _ in 0..no_of_channel_rows {
+            tv_channels.pop().unwrap();
+        }
+    }
+    tv_channels.extend(
+        doc.select(&s(&sel.channel_row))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .take(no_of_channel_rows)
+            .rev()
+            .flat_map(|mut div| {
+                let mut result = Vec::new();
+                while let Some(d) = div.prev_sibling() {
+                    if div
+                        .value()
+                        .attr("class")
+                        .unwrap_or("")
+                        .contains(&sel.channel_title_class)
+                    {
+                        break;
+                    }
+                    if let Some(chn) = find_extra_channels(&div, host, sel) {
+                        result.push(chn);
+                    }
+                    div = match ElementRef::wrap(d) {
+                        Some(d) => d,
+                        None => continue,
+                    };
+                }
+                result.reverse();
+                result
+            }),
+    );
+    tv_channels
+}
+
+fn parse_web_series(html: &str, host: &str, sel: &SelectorProfile) -> Vec<(String, String)> {
+    fn parse_anchor(a: ElementRef, host: &str) -> Option<(String, String)> {
+        let link = normalize_url(a.value().attr("href")?, host).ok()?;
+        Some((a.inner_html(), link.into_owned()))
+    }
+
+    let doc = Html::parse_document(html);
+    doc.select(&s(&sel.web_series))
+        .filter_map(|a| parse_anchor(a, host))
+        .collect()
+}
+
+fn parse_tv_show(
+    div: ElementRef,
+    host: &str,
+    no_icon: &str,
+    sel: &SelectorProfile,
+) -> Option<TvShow> {
+    let a = div.select(&s(&sel.tv_show_link)).next()?;
+    let title = fix_title(a.inner_html());
+    let url = normalize_url(a.value().attr("href")?, host)
+        .ok()?
+        .into_owned();
+    let icon = normalize_url(
+        div.select(&s(&sel.tv_show_icon))
+            .next()
+            .and_then(|img| img.value().attr("src"))
+            .unwrap_or(no_icon),
+        host,
+    )
+    .ok()?
+    .into_owned();
+    Some(TvShow { title, url, icon })
+}
+
+fn parse_tv_shows(html: &str, host: &str, no_icon: &str, sel: &SelectorProfile) -> Vec<TvShow> {
+    let doc = Html::parse_document(html);
+    doc.select(&s(&sel.tv_show))
+        .filter_map(|div| parse_tv_show(div, host, no_icon, sel))
+        .collect()
+}
+
+/// The search finds episode posts, the category pages of which are the shows they belong to.
+fn parse_search_results(
+    html: &str,
+    host: &str,
+    no_icon: &str,
+    sel: &SelectorProfile,
+) -> Vec<TvShow> {
+    fn parse_result(
+        post: ElementRef,
+        host: &str,
+        no_icon: &str,
+        sel: &SelectorProfile,
+    ) -> Option<TvShow> {
+        let a = post.select(&s(&sel.search_result_category)).next()?;
+        let title = fix_title(a.inner_html());
+        let url = normalize_url(a.value().attr("href")?, host)
+            .ok()?
+            .into_owned();
+        let icon = post
+            .select(&s(&sel.search_result_icon))
+            .next()
+            .and_then(|img| img.value().attr("src"))
+            .unwrap_or(no_icon);
+        let icon = normalize_url(icon, host).ok()?.into_owned();
+        Some(TvShow { title, url, icon })
+    }
+
+    let doc = Html::parse_document(html);
+    let mut tv_shows = Vec::<TvShow>::new();
+    for tv_show in doc
+        .select(&s(&sel.search_result))
+        .filter_map(|post| parse_result(post, host, no_icon, sel))
+    {
+        if !tv_shows.iter().any(|show| show.url == tv_show.url) {
+            tv_shows.push(tv_show);
+        }
+    }
+    tv_shows
+}
+
+fn find_episode_links(
+    html: &str,
+    host: &str,
+    sel: &SelectorProfile,
+) -> (Vec<String>, usize, usize) {
+    let doc = Html::parse_document(html);
+    let links = doc
+        .select(&s(&sel.episode_link))
+        .filter_map(|e| e.value().attr("href"))
+        .filter_map(|href| normalize_url(href, host).ok())
+        .map(|href| href.into_owned())
+        .collect::<Vec<_>>();
+    let current_page = doc
+        .select(&s(&sel.current_page))
+        .next()
+        .map(|li| li.inner_html())
+        .and_then(|p| p.parse::<usize>().ok())
+        .unwrap_or(1);
+    let last_page = doc
+        .select(&s(&sel.page_number))
+        .next_back()
+        .map(|li| li.inner_html())
+        .and_then(|p| p.parse::<usize>().ok())
+        .unwrap_or(current_page);
+    (links, current_page, last_page)
+}
+
+fn find_parts(div: ElementRef, sel: &SelectorProfile) -> Option<Episode> {
+    let provider = div.select(&s(&sel.provider_name)).next()?.inner_html();
+    let provider = VideoProvider::find(&provider)?;
+    let p = ElementRef::wrap(div.next_sibling()?)?;
+    let links = p
+        .select(&s(&sel.part_link))
+        .map(|a| (a.inner_html(), a.value().attr("href")))
+        .filter_map(|(title, opt_link)| opt_link.map(|link| (fix_title(title), link.to_owned())))
+        .collect::<Vec<_>>();
+    Some(Episode { provider, links })
+}
+
+fn find_episode_video_links(html: &str, sel: &SelectorProfile) -> (String, Vec<Episode>) {
+    let doc = Html::parse_document(html);
+    let title = doc
+        .select(&s(&sel.episode_title))
+        .next()
+        .map(|t| t.inner_html())
+        .unwrap_or_else(|| String::from("NA"));
+    let title = fix_title(title);
+    let mut parts = doc
+        .select(&s(&sel.episode_provider))
+        .filter_map(|div| find_parts(div, sel))
+        .collect::<Vec<_>>();
+    parts.sort_by_key(|e| e.provider.priority());
+    (title, parts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_episode_links, parse_search_results, SelectorProfile};
+
+    #[test]
+    fn test_find_episode_links() {
+        let html = r#"
+            <div id="content_box">
+                <article class="latestPost"><div class="latestPost-content">
+                    <h2 class="title"><a href="/anupamaa-12th-october-2023/">Anupamaa 12th October</a></h2>
+                </div></article>
+            </div>
+            <div class="nav-links">
+                <span class="page-numbers current">2</span>
+                <a class="page-numbers" href="/page/3/">3</a>
+                <a class="page-numbers next" href="/page/3/">Next</a>
+            </div>"#;
+        let (links, cur_page, last_page) = find_episode_links(
+            html,
+            "https://www.desitellybox.me/anupamaa/page/2/",
+            &SelectorProfile::default(),
+        );
+        assert_eq!(
+            links,
+            vec!["https://www.desitellybox.me/anupamaa-12th-october-2023/"]
+        );
+        assert_eq!((cur_page, last_page), (2, 3));
+    }
+
+    #[test]
+    fn test_parse_search_results() {
+        let html = r#"
+            <div id="content_box">
+                <article class="latestPost">
+                    <a href="/imlie-1st-may-2021/"><img src="/imlie.jpg"></a>
+                    <h2 class="title"><a href="/imlie-1st-may-2021/">Imlie 1st May 2021</a></h2>
+                    <span class="thecategory"><a href="/imlie/" rel="category tag">Imlie Watch Online</a></span>
+                </article>
+                <article class="latestPost">
+                    <h2 class="title"><a href="/imlie-30th-april-2021/">Imlie 30th April 2021</a></h2>
+                    <span class="thecategory"><a href="/imlie/" rel="category tag">Imlie Watch Online</a></span>
+                </article>
+                <article class="latestPost">
+                    <h2 class="title"><a href="/notice/">Notice</a></h2>
+                </article>
+            </div>"#;
+        let tv_shows = parse_search_results(
+            html,
+            "https://www.desitellybox.me",
+            "/no-icon.png",
+            &SelectorProfile::default(),
+        );
+        assert_eq!(tv_shows.len(), 1);
+        assert_eq!(tv_shows[0].title, "Imlie");
+        assert_eq!(tv_shows[0].url, "https://www.desitellybox.me/imlie/");
+        assert_eq!(tv_shows[0].icon, "https://www.desitellybox.me/imlie.jpg");
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+
+use crate::config::Config;
+use crate::fetcher::Fetcher;
+use crate::mirror::Mirror;
+use crate::models::{Episode, TvShow};
+
+pub use desi_tv::DesiTvSource;
+
+mod desi_tv;
+mod selectors;
+
+/// One page of the episode list of a tv show.
+#[derive(Debug, Clone)]
+pub struct EpisodePage {
+    /// Url of the page, it's the referer of the episode links.
+    pub url: String,
+    pub links: Vec<String>,
+    pub cur_page: usize,
+    pub last_page: usize,
+}
+
+/// A site the tv channels, shows & episodes are scraped from.
+///
+/// The state, the routes and the video providers are the same for every source.
+pub trait ContentSource: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Titles & urls of the tv channels.
+    fn list_channels(&self) -> BoxFuture<'_, anyhow::Result<Vec<(String, String)>>>;
+
+    /// Shows of the tv channel at `channel_url`.
+    fn list_shows<'a>(&'a self, channel_url: &'a str)
+        -> BoxFuture<'a, anyhow::Result<Vec<TvShow>>>;
+
+    /// Links of the episodes on the `page` (starting from 1) of a tv show.
+    fn list_episodes<'a>(
+        &'a self,
+        show_url: &'a str,
+        page: usize,
+    ) -> BoxFuture<'a, anyhow::Result<EpisodePage>>;
+
+    /// Title of an episode and the links to its parts, grouped by the video provider.
+    fn list_episode_links<'a>(
+        &'a self,
+        episode_url: &'a str,
+        referer: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<(String, Vec<Episode>)>>;
+
+    /// Shows matching `query` according to the site's own search, listed on a channel page or not.
+    fn search_shows<'a>(&'a self, query: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<TvShow>>>;
+
+    /// Re-reads the files the source is configured with, returns what has been loaded.
+    fn reload(&self) -> BoxFuture<'_, anyhow::Result<String>>;
+}
+
+pub async fn build_content_source(
+    fetcher: Fetcher,
+    config: &Config,
+    mirror: Arc<Mirror>,
+) -> Arc<dyn ContentSource> {
+    Arc::new(DesiTvSource::new(fetcher, config, mirror).await)
+}
@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use scraper::Selector;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::*;
+
+/// Css selectors & class names of the desi tv markup.
+///
+/// The defaults are compiled in, a profile file (`.toml` or `.json`) can override any of them,
+/// so that a tweak of the site's theme only needs a new profile and not a new binary.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SelectorProfile {
+    /// Bumped with every change of the profile, only used for logging & reporting.
+    pub version: u32,
+    /// Links of the main channels on the home page.
+    pub main_channel: String,
+    /// Class of the element above a group of channels, holding the group's title.
+    pub channel_title_class: String,
+    /// Title inside the `channel_title_class` element.
+    pub channel_title: String,
+    /// Columns of the channel rows at the bottom of the home page.
+    pub channel_row: String,
+    /// Link of a channel inside a `channel_row` column.
+    pub extra_channel: String,
+    /// Links on the "View All" web series page.
+    pub web_series: String,
+    /// A tv show on the page of a channel.
+    pub tv_show: String,
+    pub tv_show_link: String,
+    pub tv_show_icon: String,
+    /// Links of the episodes on the page of a tv show.
+    pub episode_link: String,
+    pub current_page: String,
+    pub page_number: String,
+    pub episode_title: String,
+    /// Header of a video provider on the page of an episode, the part links follow it.
+    pub episode_provider: String,
+    pub provider_name: String,
+    pub part_link: String,
+    /// A post on the results page of the site's search.
+    pub search_result: String,
+    /// Link of a search result to its category page, which lists the episodes of the show.
+    pub search_result_category: String,
+    pub search_result_icon: String,
+}
+
+impl Default for SelectorProfile {
+    fn default() -> Self {
+        SelectorProfile {
+            version: 1,
+            main_channel: ".post .single_page .post-content .one_sixth.column-last > a".into(),
+            channel_title_class: "home-channel-title".into(),
+            channel_title: "p".into(),
+            channel_row: ".post .single_page .post-content .one_sixth.column-last".into(),
+            extra_channel: "p.small-title a".into(),
+            web_series: ".single_page .post-content p[style] a".into(),
+            tv_show: ".tab_container #tab-0-title-1 .one_fourth".into(),
+            tv_show_link: "p.small-title a".into(),
+            tv_show_icon: "a img".into(),
+            episode_link: "#content_box .latestPost .latestPost-content h2.title a".into(),
+            current_page: ".nav-links .page-numbers.current".into(),
+            page_number: ".nav-links .page-numbers:not(.next)".into(),
+            episode_title: ".post-single-content header h1.title".into(),
+            episode_provider: ".thecontent div.buttons.btn_green".into(),
+            provider_name: "span.single-heading".into(),
+            part_link: "a".into(),
+            search_result: "#content_box article.latestPost".into(),
+            search_result_category: "a[rel~=category]".into(),
+            search_result_icon: "img".into(),
+        }
+    }
+}
+
+impl SelectorProfile {
+    /// Loads the profile from `path`, the defaults are used if there's no path or file.
+    pub async fn load(path: Option<&Path>) -> anyhow::Result<SelectorProfile> {
+        let path = match path {
+            Some(path) if path.exists() => path,
+            Some(path) => {
+                warn!("Selector profile {path:?} doesn't exist, using the defaults");
+                return Ok(SelectorProfile::default());
+            }
+            None => return Ok(SelectorProfile::default()),
+        };
+        let content = fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Couldn't read selector profile {path:?}"))?;
+        let profile: SelectorProfile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            Some("toml") => toml::from_str(&content)?,
+            _ => return Err(anyhow!("Unsupported selector profile: {path:?}")),
+        };
+        profile.validate()?;
+        info!("Loaded selector profile v{} from {path:?}", profile.version);
+        Ok(profile)
+    }
+
+    /// Makes sure every selector parses, so the scrapers can't panic on a bad profile.
+    fn validate(&self) -> anyhow::Result<()> {
+        let selectors = [
+            ("main_channel", &self.main_channel),
+            ("channel_title", &self.channel_title),
+            ("channel_row", &self.channel_row),
+            ("extra_channel", &self.extra_channel),
+            ("web_series", &self.web_series),
+            ("tv_show", &self.tv_show),
+            ("tv_show_link", &self.tv_show_link),
+            ("tv_show_icon", &self.tv_show_icon),
+            ("episode_link", &self.episode_link),
+            ("current_page", &self.current_page),
+            ("page_number", &self.page_number),
+            ("episode_title", &self.episode_title),
+            ("episode_provider", &self.episode_provider),
+            ("provider_name", &self.provider_name),
+            ("part_link", &self.part_link),
+            ("search_result", &self.search_result),
+            ("search_result_category", &self.search_result_category),
+            ("search_result_icon", &self.search_result_icon),
+        ];
+        for (name, selector) in selectors {
+            Selector::parse(selector).map_err(|e| anyhow!("Invalid {name} '{selector}': {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SelectorProfile;
+
+    #[test]
+    fn test_validate() {
+        SelectorProfile::default().validate().unwrap();
+
+        let profile = toml::from_str::<SelectorProfile>(
+            r#"
+            version = 2
+            episode_provider = ".thecontent div.buttons.btn_blue"
+            "#,
+        )
+        .unwrap();
+        profile.validate().unwrap();
+        assert_eq!(profile.episode_provider, ".thecontent div.buttons.btn_blue");
+        assert_eq!(profile.tv_show, SelectorProfile::default().tv_show);
+
+        let profile = SelectorProfile {
+            tv_show: "div[".into(),
+            ..SelectorProfile::default()
+        };
+        assert!(profile.validate().is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio::task;
+use tracing::*;
+
+use crate::app_state::AppState;
+use crate::error::HttpError;
+use crate::utils::to_rfc3339;
+
+/// How long the size of the cache folder is reused, walking the folder isn't cheap.
+const FOLDER_SIZE_TTL: Duration = Duration::from_secs(60);
+
+/// Size of the cache folder, with the time it was computed at.
+#[derive(Default)]
+pub struct FolderSizeCache {
+    size: Mutex<Option<(Instant, u64)>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusResponse {
+    version: &'static str,
+    mirror: String,
+    channels_expire_at: String,
+    cached_tv_shows: usize,
+    worker_queue_depth: usize,
+    worker_tasks_executed: usize,
+    last_channels_refresh: Option<String>,
+    last_channels_failure: Option<String>,
+    last_channels_error: Option<String>,
+    cache_folder_bytes: u64,
+    dns_cache_size: usize,
+}
+
+pub async fn healthz() -> &'static str {
+    "OK"
+}
+
+/// Snapshot of the internal state, meant for the monitoring scripts.
+pub async fn status(State(state): State<AppState>) -> Result<impl IntoResponse, HttpError> {
+    let refresh_status = state.tv_channels.refresh_status();
+    let cache_folder_bytes = state
+        .folder_size
+        .get(state.cache_folder.clone())
+        .await
+        .map_err(|e| warn!("Failed to compute the size of the cache folder: {e:?}"))
+        .unwrap_or(0);
+    Ok(Json(StatusResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        mirror: state.mirror.active(),
+        channels_expire_at: to_rfc3339(state.tv_channels.expires_at().await),
+        cached_tv_shows: state.tv_shows.len().await,
+        worker_queue_depth: state.worker.queue_depth(),
+        worker_tasks_executed: state.worker.tasks_executed(),
+        last_channels_refresh: refresh_status.last_success.map(to_rfc3339),
+        last_channels_failure: refresh_status.last_failure.map(to_rfc3339),
+        last_channels_error: refresh_status.last_error,
+        cache_folder_bytes,
+        dns_cache_size: state.dns_resolver.cache_size(),
+    }))
+}
+
+impl FolderSizeCache {
+    /// The size computed in the last minute, or the one of a new walk of the folder. The requests
+    /// which come in during a walk wait for it instead of starting their own.
+    async fn get(&self, folder: Arc<Path>) -> anyhow::Result<u64> {
+        let mut size = self.size.lock().await;
+        if let Some((computed_at, size)) = *size {
+            if computed_at.elapsed() < FOLDER_SIZE_TTL {
+                return Ok(size);
+            }
+        }
+        let computed = task::spawn_blocking(move || folder_size(&folder))
+            .await
+            .context("Walking the cache folder panicked")??;
+        *size = Some((Instant::now(), computed));
+        Ok(computed)
+    }
+}
+
+fn folder_size(path: &Path) -> std::io::Result<u64> {
+    let metadata = std::fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for child in std::fs::read_dir(path)? {
+        size += folder_size(&child?.path())?;
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::TestDir;
+
+    use super::{folder_size, FolderSizeCache};
+
+    #[tokio::test]
+    async fn test_folder_size() {
+        let folder = TestDir::new("folder_size");
+        std::fs::create_dir_all(folder.join("nested")).unwrap();
+        std::fs::write(folder.join("a.json"), "1234").unwrap();
+        std::fs::write(folder.join("nested").join("b.json"), "123456").unwrap();
+
+        assert_eq!(folder_size(&folder).unwrap(), 10);
+
+        let cache = FolderSizeCache::default();
+        assert_eq!(cache.get(folder.to_path_buf().into()).await.unwrap(), 10);
+        // The size is reused until it expires.
+        std::fs::write(folder.join("c.json"), "12").unwrap();
+        assert_eq!(cache.get(folder.to_path_buf().into()).await.unwrap(), 10);
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use redb::{Database, DatabaseError, ReadableTable, TableDefinition, TableError, TableHandle};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::RwLock;
+use tokio::{fs, task};
+use tracing::*;
+
+use crate::utils::{slugify, STATE_DB, TV_CHANNEL_FILE, TV_SHOWS_FILE};
+
+pub type Table = TableDefinition<'static, &'static str, &'static [u8]>;
+
+/// The tv channels, under [`CHANNELS_KEY`].
+pub const CHANNELS: Table = TableDefinition::new("channels");
+
+pub const CHANNELS_KEY: &str = "state";
+
+/// Episodes of the tv shows, by the cache key of the show.
+pub const TV_SHOWS: Table = TableDefinition::new("tv_shows");
+
+/// The tv shows which have been opened lately, by the cache key of the show.
+pub const OPENED_SHOWS: Table = TableDefinition::new("opened_shows");
+
+/// Playback progress of the episodes, see [`crate::history::history_key`].
+pub const WATCH_HISTORY: Table = TableDefinition::new("watch_history");
+
+/// Favorite tv shows with their new episodes, see [`crate::favorites::favorite_key`].
+pub const FAVORITES: Table = TableDefinition::new("favorites");
+
+/// The profiles, by their ids.
+pub const PROFILES: Table = TableDefinition::new("profiles");
+
+/// Embedded database in the cache folder, values are saved as json.
+///
+/// Every write is a transaction of its own, so a crash loses the last write at most.
+#[derive(Clone)]
+pub struct Store {
+    db: Arc<Database>,
+}
+
+impl Store {
+    /// The database is locked by the process which opens it, a second one fails to open it.
+    pub async fn open(cache_folder: &Path) -> anyhow::Result<Store> {
+        let file = cache_folder.join(STATE_DB);
+        info!("Opening state database {file:?}");
+        let db = match task::spawn_blocking(move || Database::create(file)).await? {
+            Ok(db) => db,
+            Err(DatabaseError::DatabaseAlreadyOpen) => {
+                return Err(anyhow!(
+                    "State database in {cache_folder:?} is in use by another process, \
+                    stop the server using this cache folder first"
+                ));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let store = Store { db: Arc::new(db) };
+        store.migrate(cache_folder).await?;
+        Ok(store)
+    }
+
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        table: Table,
+        key: &str,
+    ) -> anyhow::Result<Option<T>> {
+        let key = key.to_owned();
+        let value = self
+            .blocking(move |db| {
+                let tx = db.begin_read()?;
+                let table = match tx.open_table(table) {
+                    Ok(table) => table,
+                    Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                };
+                let value = table.get(key.as_str())?.map(|value| value.value().to_vec());
+                Ok(value)
+            })
+            .await?;
+        Ok(value.map(|v| serde_json::from_slice(&v)).transpose()?)
+    }
+
+    /// Every entry of the table, the ones which don't deserialize are skipped.
+    pub async fn entries<T: DeserializeOwned>(
+        &self,
+        table: Table,
+    ) -> anyhow::Result<Vec<(String, T)>> {
+        let entries = self
+            .blocking(move |db| {
+                let tx = db.begin_read()?;
+                let table = match tx.open_table(table) {
+                    Ok(table) => table,
+                    Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+                    Err(e) => return Err(e.into()),
+                };
+                let mut entries = Vec::new();
+                for entry in table.iter()? {
+                    let (key, value) = entry?;
+                    entries.push((key.value().to_owned(), value.value().to_vec()));
+                }
+                Ok(entries)
+            })
+            .await?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|(key, value)| match serde_json::from_slice(&value) {
+                Ok(value) => Some((key, value)),
+                Err(e) => {
+                    warn!("Skipping {key} of {}: {e}", table.name());
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Every entry of the table under the key which `key_of` gives it, the entries which were
+    /// saved under another key (by an older version) are moved.
+    pub async fn keyed_entries<T: Serialize + DeserializeOwned>(
+        &self,
+        table: Table,
+        key_of: impl Fn(&T) -> String,
+    ) -> anyhow::Result<HashMap<String, T>> {
+        let entries = self.entries::<T>(table).await?;
+        let moved = entries
+            .iter()
+            .filter(|(key, value)| *key != key_of(value))
+            .count();
+        let entries = entries
+            .into_iter()
+            .map(|(_, value)| (key_of(&value), value))
+            .collect::<HashMap<_, _>>();
+        if moved > 0 {
+            info!(
+                "Moving {moved} entries of {} to their new keys",
+                table.name()
+            );
+            self.replace_all(table, entries.iter().map(|(k, v)| (k.clone(), v)))
+                .await?;
+        }
+        Ok(entries)
+    }
+
+    pub async fn put<T: Serialize>(
+        &self,
+        table: Table,
+        key: &str,
+        value: &T,
+    ) -> anyhow::Result<()> {
+        let entry = (key.to_owned(), serde_json::to_vec(value)?);
+        self.write(table, false, vec![entry]).await
+    }
+
+    /// Returns false if there was nothing to remove.
+    pub async fn remove(&self, table: Table, key: &str) -> anyhow::Result<bool> {
+        let key = key.to_owned();
+        self.blocking(move |db| {
+            let tx = db.begin_write()?;
+            let removed = tx.open_table(table)?.remove(key.as_str())?.is_some();
+            tx.commit()?;
+            Ok(removed)
+        })
+        .await
+    }
+
+    /// Swaps the content of the table with `entries`, in one transaction.
+    pub async fn replace_all<T: Serialize>(
+        &self,
+        table: Table,
+        entries: impl IntoIterator<Item = (String, T)>,
+    ) -> anyhow::Result<()> {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| Ok((key, serde_json::to_vec(&value)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.write(table, true, entries).await
+    }
+
+    async fn write(
+        &self,
+        table: Table,
+        clear: bool,
+        entries: Vec<(String, Vec<u8>)>,
+    ) -> anyhow::Result<()> {
+        self.blocking(move |db| {
+            let tx = db.begin_write()?;
+            {
+                let mut table = tx.open_table(table)?;
+                if clear {
+                    table.retain(|_, _| false)?;
+                }
+                for (key, value) in &entries {
+                    table.insert(key.as_str(), value.as_slice())?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Database) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let db = self.db.clone();
+        task::spawn_blocking(move || f(&db)).await?
+    }
+
+    /// Moves the state of the json files, which the database has replaced, into the database.
+    async fn migrate(&self, cache_folder: &Path) -> anyhow::Result<()> {
+        let channels_file = cache_folder.join(TV_CHANNEL_FILE);
+        if let Some(channels) = read_json(&channels_file).await {
+            info!("Migrating {channels_file:?} into the state database");
+            self.put(CHANNELS, CHANNELS_KEY, &channels).await?;
+            mark_migrated(&channels_file).await;
+        }
+
+        let tv_shows_file = cache_folder.join(TV_SHOWS_FILE);
+        if let Some(mut tv_shows) = read_json(&tv_shows_file).await {
+            info!("Migrating {tv_shows_file:?} into the state database");
+            if let Some(serde_json::Value::Object(mut map)) =
+                tv_shows.get_mut("map").map(|m| m.take())
+            {
+                let mut upgraded = 0;
+                for tv_show in map.values_mut() {
+                    upgraded += upgrade_tv_show(tv_show) as usize;
+                }
+                if upgraded > 0 {
+                    info!("Converted {upgraded} tv shows saved before the episodes had ids");
+                }
+                self.replace_all(TV_SHOWS, map).await?;
+            }
+            mark_migrated(&tv_shows_file).await;
+        }
+        Ok(())
+    }
+}
+
+/// Episodes used to be saved as `(title, parts)` pairs, they're given the slug of their title as
+/// id. It's unlikely to be the id of their page, so they're replaced on the next refresh.
+fn upgrade_tv_show(tv_show: &mut serde_json::Value) -> bool {
+    let Some(serde_json::Value::Array(episodes)) = tv_show.get_mut("episodes") else {
+        return false;
+    };
+    let mut upgraded = false;
+    for episode in episodes.iter_mut() {
+        if let serde_json::Value::Array(pair) = episode {
+            if let [serde_json::Value::String(title), parts] = pair.as_mut_slice() {
+                *episode = serde_json::json!({
+                    "id": slugify(title),
+                    "title": title,
+                    "parts": parts.take(),
+                });
+                upgraded = true;
+            }
+        }
+    }
+    upgraded
+}
+
+/// Every entry of a table, kept in memory & written through to the store.
+///
+/// A failed write is only logged, like the one of the tv shows: the change is kept in memory
+/// and saved along with the next change of the entry.
+pub struct KeyedStateWrapper<T> {
+    state: RwLock<BTreeMap<String, T>>,
+    store: Store,
+    table: Table,
+}
+
+impl<T> KeyedStateWrapper<T>
+where
+    T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Loads the table, under the keys which `key_of` gives the entries.
+    pub async fn load(store: Store, table: Table, key_of: impl Fn(&T) -> String) -> Self {
+        let map = store
+            .keyed_entries(table, key_of)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Loading of {} failed: {e:?}", table.name());
+                HashMap::new()
+            });
+        debug!("Loaded {} entries of {}", map.len(), table.name());
+        KeyedStateWrapper {
+            state: RwLock::new(map.into_iter().collect()),
+            store,
+            table,
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<T> {
+        self.state.read().await.get(key).cloned()
+    }
+
+    /// Every entry, ordered by key.
+    pub async fn all(&self) -> Vec<T> {
+        self.state.read().await.values().cloned().collect()
+    }
+
+    pub async fn filter(&self, predicate: impl Fn(&T) -> bool) -> Vec<T> {
+        let state = self.state.read().await;
+        state.values().filter(|v| predicate(v)).cloned().collect()
+    }
+
+    pub async fn put(&self, key: String, value: T) {
+        self.save(&key, self.store.put(self.table, &key, &value).await);
+        self.state.write().await.insert(key, value);
+    }
+
+    /// Changes an entry, unless it has been removed in the meantime.
+    pub async fn update(&self, key: &str, f: impl FnOnce(&mut T)) {
+        let mut state = self.state.write().await;
+        if let Some(value) = state.get_mut(key) {
+            f(value);
+            let value = value.clone();
+            drop(state);
+            self.save(key, self.store.put(self.table, key, &value).await);
+        }
+    }
+
+    /// Returns false if there was nothing to remove.
+    pub async fn remove(&self, key: &str) -> bool {
+        let removed = self.state.write().await.remove(key).is_some();
+        if removed {
+            self.save(key, self.store.remove(self.table, key).await.map(|_| ()));
+        }
+        removed
+    }
+
+    pub async fn remove_where(&self, predicate: impl Fn(&T) -> bool) {
+        let mut state = self.state.write().await;
+        let keys = state
+            .iter()
+            .filter(|(_, v)| predicate(v))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in &keys {
+            state.remove(key);
+        }
+        drop(state);
+        for key in keys {
+            self.save(&key, self.store.remove(self.table, &key).await.map(|_| ()));
+        }
+    }
+
+    fn save(&self, key: &str, result: anyhow::Result<()>) {
+        if let Err(e) = result {
+            error!("Failed to save {key} of {}: {e:?}", self.table.name());
+        }
+    }
+}
+
+async fn read_json(file: &Path) -> Option<serde_json::Value> {
+    let content = fs::read_to_string(file).await.ok()?;
+    match serde_json::from_str(&content) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Couldn't migrate {file:?}: {e}");
+            mark_migrated(file).await;
+            None
+        }
+    }
+}
+
+/// The old file is kept around, but it's not picked up again.
+async fn mark_migrated(file: &Path) {
+    let mut migrated = file.as_os_str().to_owned();
+    migrated.push(".migrated");
+    if let Err(e) = fs::rename(file, &migrated).await {
+        error!("Failed to rename the migrated {file:?}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::models::TvShowEpisodes;
+    use crate::utils::{TestDir, TV_SHOWS_FILE};
+
+    use super::{KeyedStateWrapper, Store, FAVORITES, TV_SHOWS};
+
+    #[tokio::test]
+    async fn test_migration() {
+        let cache_dir = TestDir::new("store");
+        // Saved before the episodes had ids.
+        let tv_shows = json!({
+            "map": {
+                "Anupamaa:https://example.com/anupamaa/": {
+                    "episodes": [[
+                        "Anupamaa 12th October 2023 Video Episode",
+                        [{ "provider": "TVLogy", "links": [["Part 1", "https://example.com/p1"]] }],
+                    ]],
+                    "cur_page": 1,
+                    "last_page": 5,
+                },
+            },
+            "expires_at": { "secs_since_epoch": 1, "nanos_since_epoch": 0 },
+        });
+        std::fs::write(cache_dir.join(TV_SHOWS_FILE), tv_shows.to_string()).unwrap();
+
+        let store = Store::open(&cache_dir).await.unwrap();
+        assert!(!cache_dir.join(TV_SHOWS_FILE).exists());
+        let entries = store.entries::<TvShowEpisodes>(TV_SHOWS).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        let (key, tv_show) = &entries[0];
+        assert_eq!(key, "Anupamaa:https://example.com/anupamaa/");
+        assert_eq!(tv_show.last_page, 5);
+        assert_eq!(
+            tv_show.episodes[0].id,
+            "anupamaa-12th-october-2023-video-episode"
+        );
+        assert_eq!(tv_show.episodes[0].parts[0].links[0].0, "Part 1");
+
+        assert!(store.remove(TV_SHOWS, &entries[0].0).await.unwrap());
+        assert!(!store.remove(TV_SHOWS, &entries[0].0).await.unwrap());
+        store.put(TV_SHOWS, "key", &json!(42)).await.unwrap();
+        store
+            .replace_all(TV_SHOWS, vec![("other".to_owned(), 7)])
+            .await
+            .unwrap();
+        let entries = store.entries::<u32>(TV_SHOWS).await.unwrap();
+        assert_eq!(entries, vec![("other".to_owned(), 7)]);
+    }
+
+    #[tokio::test]
+    async fn test_keyed_entries() {
+        let cache_dir = TestDir::new("keyed_entries");
+        let store = Store::open(&cache_dir).await.unwrap();
+        store
+            .put(
+                FAVORITES,
+                "star-plus/anupamaa",
+                &json!({ "show": "anupamaa" }),
+            )
+            .await
+            .unwrap();
+
+        let key_of =
+            |value: &serde_json::Value| format!("default/{}", value["show"].as_str().unwrap());
+        let entries = store.keyed_entries(FAVORITES, key_of).await.unwrap();
+        assert!(entries.contains_key("default/anupamaa"));
+        let saved = store.entries::<serde_json::Value>(FAVORITES).await.unwrap();
+        assert_eq!(saved[0].0, "default/anupamaa");
+    }
+
+    #[tokio::test]
+    async fn test_keyed_state() {
+        let cache_dir = TestDir::new("keyed_state");
+        let store = Store::open(&cache_dir).await.unwrap();
+        let key_of = |value: &u32| format!("{}/{value}", value % 2);
+        let state = KeyedStateWrapper::load(store.clone(), FAVORITES, key_of).await;
+        for value in 1..=4 {
+            state.put(key_of(&value), value).await;
+        }
+        state.update("1/3", |value| *value = 5).await;
+        state.update("1/7", |value| *value = 9).await;
+        state.remove_where(|value| value % 2 == 0).await;
+        assert_eq!(state.all().await, vec![1, 5]);
+
+        // Loaded again, the changed entry moves to its new key.
+        let state = KeyedStateWrapper::load(store, FAVORITES, key_of).await;
+        assert_eq!(state.get("1/5").await, Some(5));
+        assert!(state.remove("1/1").await);
+        assert!(!state.remove("1/1").await);
+    }
+}
@@ -1,55 +1,138 @@
 use std::time::Instant;
 
+use anyhow::anyhow;
+use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Json;
 use futures::{stream, StreamExt};
 use linked_hash_map::LinkedHashMap;
-use reqwest::header;
-use scraper::{ElementRef, Html};
 use serde::{Deserialize, Serialize};
 use tracing::*;
 
-use crate::error::HttpError;
-use crate::http_util::{http_client, normalize_url, s, PARALLELISM};
+use crate::app_state::AppState;
+use crate::config::Config;
+use crate::error::{HttpError, ServerError};
+use crate::metrics::time_step;
 use crate::models::TvShow;
-use crate::utils::{encode_uri_component, fix_title};
-use crate::worker;
+use crate::profiles::CurrentProfile;
+use crate::sources::ContentSource;
+use crate::utils::{encode_uri_component, slugify, url_id};
 
-pub const DESI_TV: &str = "https://www.yodesitv.info";
-
-const NO_OF_CHANNEL_ROWS: usize = 2;
-
-const BANNED_CHANNELS: &[&str] = &["Star Jalsha", "Star Pravah", "Star Vijay", "Bindass TV"];
-
-pub const NO_ICON: &str =
-    "https://www.yodesitv.info/wp-content/uploads/2016/11/no-thumbnail-370x208.jpg";
+pub use state::TvChannelStateWrapper;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-struct TvShowResponse {
+pub struct TvShowResponse {
+    id: String,
     title: String,
     icon: String,
 }
 
-pub async fn channel_home() -> Result<impl IntoResponse, HttpError> {
-    async fn _channel_home() -> anyhow::Result<LinkedHashMap<String, Vec<TvShow>>> {
-        let state = state::STATE
-            .get()
-            .ok_or_else(|| anyhow::anyhow!("Wtf, Channel state is not initialized"))?;
-        if let Some(tv_channels) = state.get_all_channels().await {
-            Ok(tv_channels)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TvChannelResponse {
+    id: String,
+    title: String,
+    tv_shows: Vec<TvShowResponse>,
+}
+
+/// The channels of the home page, without the ones which the profile hides.
+pub async fn channel_home(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+) -> Result<impl IntoResponse, HttpError> {
+    let channels = tv_channels(&state)
+        .await?
+        .into_iter()
+        .filter(|(title, _)| !profile.is_hidden(title))
+        .collect::<LinkedHashMap<_, _>>();
+    Ok(Json(channels))
+}
+
+/// Same as `/home`, but a list which carries the ids of the channels too.
+pub async fn channel_list(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
+) -> Result<impl IntoResponse, HttpError> {
+    let channels = tv_channels(&state)
+        .await?
+        .into_iter()
+        .filter(|(title, _)| !profile.is_hidden(title))
+        .map(|(title, tv_shows)| TvChannelResponse {
+            id: slugify(&title),
+            title,
+            tv_shows,
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(channels))
+}
+
+/// Returns the cached tv channels, or downloads them again if they have expired.
+pub async fn tv_channels(
+    state: &AppState,
+) -> anyhow::Result<LinkedHashMap<String, Vec<TvShowResponse>>> {
+    load_tv_channels(state, false).await
+}
+
+/// Downloads the tv channels again, even if the cached ones haven't expired yet.
+///
+/// Fails with [`ServerError::Busy`] while another refresh is running.
+pub async fn refresh_tv_channels(
+    state: &AppState,
+) -> anyhow::Result<LinkedHashMap<String, Vec<TvShowResponse>>> {
+    load_tv_channels(state, true).await
+}
+
+async fn load_tv_channels(
+    state: &AppState,
+    force: bool,
+) -> anyhow::Result<LinkedHashMap<String, Vec<TvShowResponse>>> {
+    async fn _channel_home(
+        state: AppState,
+        force: bool,
+    ) -> anyhow::Result<LinkedHashMap<String, Vec<TvShow>>> {
+        let cached = if force {
+            None
         } else {
-            info!("TV channels list have expired, time to refresh it");
-            let start = Instant::now();
-            let tv_channels = download_tv_channels().await?;
-            state.update_state(tv_channels.iter()).await?;
-            info!("Time taken to download the tv shows: {:?}", start.elapsed());
-            Ok(tv_channels)
+            state.tv_channels.get_all_channels().await
+        };
+        match cached {
+            Some((tv_channels, true)) => Ok(tv_channels),
+            Some((tv_channels, false)) => {
+                // The expired channels are served while they're downloaded again.
+                refresh_in_background(&state);
+                Ok(tv_channels)
+            }
+            None if force => {
+                if !state.tv_channels.try_begin_refresh() {
+                    let msg = "TV channels are being refreshed already".to_owned();
+                    return Err(ServerError::Busy(msg).into());
+                }
+                info!("A refresh of the tv channels was forced");
+                locked_download(&state).await
+            }
+            None => {
+                if state.tv_channels.try_begin_refresh() {
+                    info!("TV channels list is empty, time to refresh it");
+                    return locked_download(&state).await;
+                }
+                info!("TV channels list is empty, waiting on the running refresh");
+                state.tv_channels.wait_for_refresh().await;
+                match state.tv_channels.get_all_channels().await {
+                    Some((tv_channels, _)) => Ok(tv_channels),
+                    None => {
+                        let error = state.tv_channels.refresh_status().last_error;
+                        let error = error.unwrap_or_else(|| "no channel found".to_owned());
+                        Err(anyhow!("Refresh of the tv channels failed: {error}"))
+                    }
+                }
+            }
         }
     }
 
-    state::init().await;
-
-    let channels = worker::run(_channel_home()).await?;
+    let channels = state
+        .worker
+        .run(_channel_home(state.clone(), force))
+        .await?;
+    state.follow_mirror().await?;
     let response = channels
         .into_iter()
         .map(|(title, tv_shows)| {
@@ -57,52 +140,118 @@ pub async fn channel_home() -> Result<impl IntoResponse, HttpError> {
                 title,
                 tv_shows
                     .into_iter()
-                    .map(|TvShow { title, icon, .. }| TvShowResponse { title, icon })
+                    .map(|TvShow { title, url, icon }| TvShowResponse {
+                        id: url_id(&url),
+                        title,
+                        icon,
+                    })
                     .collect::<Vec<_>>(),
             )
         })
         .collect::<LinkedHashMap<_, _>>();
-    Ok(Json(response))
+    Ok(response)
+}
+
+fn refresh_in_background(state: &AppState) {
+    if !state.tv_channels.begin_refresh() {
+        return;
+    }
+    info!("TV channels list has expired, refreshing it in the background");
+    let state = state.clone();
+    // Not run by the worker, the reads of the expired channels mustn't queue behind it.
+    tokio::spawn(async move { guarded_refresh(&state).await });
+}
+
+/// Downloads the channels ahead of their expiry, unless a refresh is running already.
+pub async fn prefetch_tv_channels(state: &AppState) {
+    if state.tv_channels.begin_refresh() {
+        guarded_refresh(state).await;
+    }
 }
 
-#[instrument]
-async fn download_tv_channels() -> anyhow::Result<LinkedHashMap<String, Vec<TvShow>>> {
-    info!("Loading TV channels from {DESI_TV}");
-    let html = http_client().get(DESI_TV).send().await?.text().await?;
-    let mut tv_channels = parse_channels(&html);
-    if tv_channels
-        .last()
-        .map(|(title, _)| title.contains("View All"))
-        .unwrap_or(false)
-    {
-        let (_, link) = tv_channels.pop().unwrap();
-        let html = http_client()
-            .get(&link)
-            .header(header::REFERER, DESI_TV)
-            .send()
-            .await?
-            .text()
-            .await?;
-        tv_channels.extend(parse_web_series(&html, &link));
+/// Downloads the channels, once the refresh has been taken with `begin_refresh`.
+async fn guarded_refresh(state: &AppState) {
+    match locked_download(state).await {
+        Ok(_) => {
+            if let Err(e) = state.follow_mirror().await {
+                warn!("Failed to follow the mirror: {e:?}");
+            }
+        }
+        Err(e) => warn!("Refresh of the tv channels failed: {e:?}"),
     }
-    let tv_channels = tv_channels
+}
+
+/// Downloads the channels & releases the refresh taken by the caller.
+async fn locked_download(state: &AppState) -> anyhow::Result<LinkedHashMap<String, Vec<TvShow>>> {
+    let result = download_and_save(state).await;
+    state.tv_channels.end_refresh();
+    result
+}
+
+async fn download_and_save(state: &AppState) -> anyhow::Result<LinkedHashMap<String, Vec<TvShow>>> {
+    let start = Instant::now();
+    let previous = state.tv_channels.last_known_channels().await;
+    let tv_channels = time_step(
+        "download_tv_channels",
+        None,
+        download_tv_channels(state.source.as_ref(), &state.config, &previous),
+    )
+    .await
+    .inspect_err(|e| state.tv_channels.refresh_failed(e))?;
+    state.tv_channels.refresh_succeeded();
+    state.tv_channels.update_state(tv_channels.iter()).await?;
+    info!("Time taken to download the tv shows: {:?}", start.elapsed());
+    Ok(tv_channels)
+}
+
+/// Downloads the shows of every channel, the channels which fail keep their `previous` shows.
+#[instrument(skip_all)]
+async fn download_tv_channels(
+    source: &dyn ContentSource,
+    config: &Config,
+    previous: &LinkedHashMap<String, Vec<TvShow>>,
+) -> anyhow::Result<LinkedHashMap<String, Vec<TvShow>>> {
+    info!("Loading TV channels from {}", source.name());
+    let tv_channels = source
+        .list_channels()
+        .await?
         .into_iter()
-        .filter(|(title, _)| !BANNED_CHANNELS.contains(&(title as &str)))
+        .filter(|(title, _)| !config.banned_channels.contains(title))
         .collect::<Vec<_>>();
     info!("Tv channels found: {}", tv_channels.len());
 
-    let mut tv_shows_map = stream::iter(tv_channels)
+    let tv_shows_map = stream::iter(tv_channels)
         .map(|(title, url)| async move {
-            let tv_shows = download_tv_shows(&url).await;
-            match tv_shows {
-                Ok(tv_shows) => Some((title, tv_shows)),
-                Err(e) => {
-                    warn!("Failed to download tv shows for {title}, {e}");
+            let error = match source.list_shows(&url).await {
+                Ok(tv_shows) if !tv_shows.is_empty() => {
+                    let tv_shows = tv_shows
+                        .into_iter()
+                        .map(|mut tv_show| {
+                            tv_show.icon =
+                                format!("/media?url={}", encode_uri_component(&tv_show.icon));
+                            tv_show
+                        })
+                        .collect();
+                    return Some((title, tv_shows));
+                }
+                Ok(_) => "no tv show was found".to_owned(),
+                Err(e) => e.to_string(),
+            };
+            match previous.get(&title) {
+                Some(tv_shows) => {
+                    warn!(
+                        "Failed to download tv shows for {title}, {error}; keeping the last {} shows",
+                        tv_shows.len()
+                    );
+                    Some((title, tv_shows.clone()))
+                }
+                None => {
+                    warn!("Failed to download tv shows for {title}, {error}");
                     None
                 }
             }
         })
-        .buffered(PARALLELISM)
+        .buffered(config.parallelism)
         .filter_map(|x| async { x })
         .collect::<LinkedHashMap<_, _>>()
         .await;
@@ -110,248 +259,359 @@ async fn download_tv_channels() -> anyhow::Result<LinkedHashMap<String, Vec<TvSh
         "Total Tv Shows: {}",
         tv_shows_map.values().flatten().count()
     );
-
-    for (_, tv_shows) in &mut tv_shows_map {
-        for tv_show in tv_shows {
-            tv_show.icon = format!("/media?url={}", encode_uri_component(&tv_show.icon));
-        }
-    }
     Ok(tv_shows_map)
 }
 
-fn parse_channels(html: &str) -> Vec<(String, String)> {
-    fn find_main_channels(a: ElementRef) -> Option<(String, String)> {
-        let mut title = None;
-        let link = normalize_url(a.value().attr("href")?, DESI_TV).ok()?;
-        let mut prev = a.parent()?.prev_sibling();
-        while let Some(p) = prev {
-            let p_class = p
-                .value()
-                .as_element()
-                .and_then(|p_ele| p_ele.attr("class"))
-                .unwrap_or("");
-            if p_class.contains("home-channel-title") {
-                let p = ElementRef::wrap(p)?;
-                let html = p.select(&s("p")).next()?.inner_html();
-                title = Some(fix_title(html));
-                break;
-            }
-            prev = p.prev_sibling();
-        }
-        Some((title?, link.into_owned()))
+mod state {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Mutex, RwLock as StdRwLock};
+    use std::time::{Duration, SystemTime};
+
+    use linked_hash_map::LinkedHashMap;
+    use serde::*;
+    use tokio::sync::{Notify, RwLock};
+    use tracing::*;
+
+    use crate::models::TvShow;
+    use crate::search::{SearchIndex, SearchResult, SITE_SEARCH_CHANNEL};
+    use crate::store::{Store, CHANNELS, CHANNELS_KEY};
+    use crate::utils::{expiry_time, slugify, url_id};
+
+    const MAX_FOUND_SHOWS: usize = 200;
+
+    const REFRESH_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+    pub struct TvChannelStateWrapper {
+        state: RwLock<TvChannelState>,
+        store: Store,
+        expiry: Duration,
+        refresh_status: Mutex<RefreshStatus>,
+        search_index: StdRwLock<SearchIndex>,
+        refreshing: AtomicBool,
+        refresh_done: Notify,
     }
 
-    fn find_extra_channels(div: &ElementRef) -> Option<(String, String)> {
-        let a = div.select(&s("p.small-title a")).next()?;
-        let link = normalize_url(a.value().attr("href")?, DESI_TV).ok()?;
-        Some((a.inner_html(), link.into_owned()))
+    /// Outcome of the latest downloads of the tv channels.
+    #[derive(Debug, Clone, Default)]
+    pub struct RefreshStatus {
+        pub last_success: Option<SystemTime>,
+        pub last_failure: Option<SystemTime>,
+        pub last_error: Option<String>,
     }
 
-    let mut tv_channels = Vec::new();
-    let doc = Html::parse_document(html);
-    tv_channels.extend(
-        doc.select(&s(
-            ".post .single_page .post-content .one_sixth.column-last > a",
-        ))
-        .filter_map(find_main_channels),
-    );
-    if tv_channels.len() > NO_OF_CHANNEL_ROWS {
-        for _ in 0..NO_OF_CHANNEL_ROWS {
-            tv_channels.pop().unwrap();
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    struct TvChannelState {
+        channels: LinkedHashMap<String, Vec<TvShow>>,
+        expires_at: SystemTime,
+        /// Shows found by the site search, they don't expire with the channels.
+        #[serde(default)]
+        found_shows: Vec<TvShow>,
+    }
+
+    impl TvChannelState {
+        fn find_channel(&self, tv_channel: &str) -> Option<(&str, &Vec<TvShow>)> {
+            if tv_channel == SITE_SEARCH_CHANNEL || tv_channel == slugify(SITE_SEARCH_CHANNEL) {
+                return Some((SITE_SEARCH_CHANNEL, &self.found_shows));
+            }
+            self.channels
+                .iter()
+                .find(|(title, _)| *title == tv_channel)
+                .or_else(|| {
+                    self.channels
+                        .iter()
+                        .find(|(title, _)| slugify(title) == tv_channel)
+                })
+                .map(|(title, shows)| (title.as_str(), shows))
         }
     }
-    tv_channels.extend(
-        doc.select(&s(
-            ".post .single_page .post-content .one_sixth.column-last",
-        ))
-        .collect::<Vec<_>>()
-        .into_iter()
-        .rev()
-        .take(NO_OF_CHANNEL_ROWS)
-        .rev()
-        .flat_map(|mut div| {
-            let mut result = Vec::new();
-            while let Some(d) = div.prev_sibling() {
-                if div
-                    .value()
-                    .attr("class")
-                    .unwrap_or("")
-                    .contains("home-channel-title")
-                {
-                    break;
-                }
-                if let Some(chn) = find_extra_channels(&div) {
-                    result.push(chn);
-                }
-                div = match ElementRef::wrap(d) {
-                    Some(d) => d,
-                    None => continue,
-                };
+
+    impl TvChannelStateWrapper {
+        pub async fn load(store: Store, expiry: Duration) -> Self {
+            let tv_channels = store
+                .get::<TvChannelState>(CHANNELS, CHANNELS_KEY)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Couldn't load the saved tv channels: {e}");
+                    None
+                })
+                .unwrap_or_else(|| TvChannelState {
+                    channels: LinkedHashMap::new(),
+                    expires_at: SystemTime::now(),
+                    found_shows: Vec::new(),
+                });
+            let search_index = SearchIndex::build(&tv_channels.channels);
+            TvChannelStateWrapper {
+                state: RwLock::new(tv_channels),
+                store,
+                expiry,
+                refresh_status: Mutex::new(RefreshStatus::default()),
+                search_index: StdRwLock::new(search_index),
+                refreshing: AtomicBool::new(false),
+                refresh_done: Notify::new(),
             }
-            result.reverse();
-            result
-        }),
-    );
-    tv_channels
-}
+        }
 
-fn parse_web_series(html: &str, host: &str) -> Vec<(String, String)> {
-    fn parse_anchor(a: ElementRef, host: &str) -> Option<(String, String)> {
-        let link = normalize_url(a.value().attr("href")?, host).ok()?;
-        Some((a.inner_html(), link.into_owned()))
-    }
+        pub async fn expires_at(&self) -> SystemTime {
+            self.state.read().await.expires_at
+        }
 
-    let doc = Html::parse_document(html);
-    doc.select(&s(".single_page .post-content p[style] a"))
-        .filter_map(|a| parse_anchor(a, host))
-        .collect()
-}
+        pub fn refresh_status(&self) -> RefreshStatus {
+            self.refresh_status.lock().unwrap().clone()
+        }
 
-async fn download_tv_shows(url: &str) -> anyhow::Result<Vec<TvShow>> {
-    fn parse_tv_show(div: ElementRef, host: &str) -> Option<TvShow> {
-        let a = div.select(&s("p.small-title a")).next()?;
-        let title = fix_title(a.inner_html());
-        let url = normalize_url(a.value().attr("href")?, host)
-            .ok()?
-            .into_owned();
-        let icon = normalize_url(
-            div.select(&s("a img"))
-                .next()
-                .and_then(|img| img.value().attr("src"))
-                .unwrap_or(NO_ICON),
-            host,
-        )
-        .ok()?
-        .into_owned();
-        Some(TvShow { title, url, icon })
-    }
-    fn parse_tv_shows(html: &str, host: &str) -> Vec<TvShow> {
-        let doc = Html::parse_document(html);
-        doc.select(&s(".tab_container #tab-0-title-1 .one_fourth"))
-            .filter_map(|div| parse_tv_show(div, host))
-            .collect()
-    }
-    info!("Downloading {url}");
-    let html = http_client()
-        .get(url)
-        .header(header::REFERER, DESI_TV)
-        .send()
-        .await?
-        .text()
-        .await?;
-    Ok(parse_tv_shows(&html, url))
-}
+        pub fn refresh_succeeded(&self) {
+            self.refresh_status.lock().unwrap().last_success = Some(SystemTime::now());
+        }
 
-pub async fn get_tv_show(tv_channel: &str, tv_show: &str) -> Option<TvShow> {
-    state::init().await;
-    state::STATE.get()?.get_tv_show(tv_channel, tv_show).await
-}
+        pub fn refresh_failed(&self, error: &anyhow::Error) {
+            let mut status = self.refresh_status.lock().unwrap();
+            status.last_failure = Some(SystemTime::now());
+            status.last_error = Some(error.to_string());
+        }
 
-mod state {
-    use std::path::PathBuf;
-    use std::time::SystemTime;
+        /// The saved channels, and whether they're still fresh, unless there are none at all.
+        pub async fn get_all_channels(&self) -> Option<(LinkedHashMap<String, Vec<TvShow>>, bool)> {
+            let read = self.state.read().await;
+            if read.channels.is_empty() {
+                return None;
+            }
+            let fresh = read.expires_at >= SystemTime::now();
+            Some((read.channels.clone(), fresh))
+        }
 
-    use anyhow::anyhow;
-    use linked_hash_map::LinkedHashMap;
-    use once_cell::sync::OnceCell;
-    use serde::*;
-    use tokio::fs;
-    use tokio::sync::RwLock;
-    use tracing::*;
+        pub async fn is_empty(&self) -> bool {
+            self.state.read().await.channels.is_empty()
+        }
 
-    use crate::models::TvShow;
-    use crate::utils::{cache_folder, expiry_time, EXPIRY, TV_CHANNEL_FILE};
+        /// The saved channels, expired or not.
+        pub async fn last_known_channels(&self) -> LinkedHashMap<String, Vec<TvShow>> {
+            self.state.read().await.channels.clone()
+        }
 
-    pub static STATE: OnceCell<TvChannelStateWrapper> = OnceCell::new();
+        /// Returns false if a background refresh is running already, or the last one has
+        /// failed too recently to try again.
+        pub fn begin_refresh(&self) -> bool {
+            let recently_failed = self
+                .refresh_status()
+                .last_failure
+                .and_then(|failed_at| failed_at.elapsed().ok())
+                .is_some_and(|elapsed| elapsed < REFRESH_RETRY_DELAY);
+            !recently_failed && self.try_begin_refresh()
+        }
 
-    pub struct TvChannelStateWrapper(RwLock<TvChannelState>);
+        /// Returns false if a refresh is running already, the recent failures don't matter.
+        pub fn try_begin_refresh(&self) -> bool {
+            !self.refreshing.swap(true, Ordering::AcqRel)
+        }
 
-    #[derive(Debug, Clone, Deserialize, Serialize)]
-    struct TvChannelState {
-        channels: LinkedHashMap<String, Vec<TvShow>>,
-        expires_at: SystemTime,
-    }
+        pub fn end_refresh(&self) {
+            self.refreshing.store(false, Ordering::Release);
+            self.refresh_done.notify_waiters();
+        }
 
-    impl TvChannelStateWrapper {
-        pub async fn get_all_channels(&self) -> Option<LinkedHashMap<String, Vec<TvShow>>> {
-            let read = self.0.read().await;
-            if read.expires_at >= SystemTime::now() {
-                Some(read.channels.clone())
-            } else {
-                if !read.channels.is_empty() {
-                    drop(read);
-                    self.0.write().await.channels.clear();
-                    self.dump().await.ok();
+        pub fn is_refreshing(&self) -> bool {
+            self.refreshing.load(Ordering::Acquire)
+        }
+
+        /// Returns once no refresh is running.
+        pub async fn wait_for_refresh(&self) {
+            loop {
+                // Registered before the check, so that the end of the refresh isn't missed.
+                let done = self.refresh_done.notified();
+                if !self.is_refreshing() {
+                    return;
                 }
-                None
+                done.await;
             }
         }
 
+        /// Finds a tv show by the ids of the channel & the show, or by their titles.
         pub async fn get_tv_show(&self, tv_channel: &str, tv_show: &str) -> Option<TvShow> {
-            self.0
-                .read()
-                .await
-                .channels
-                .get(tv_channel)
-                .and_then(|v| v.iter().find(|show| show.title == tv_show))
+            let read = self.state.read().await;
+            let (_, tv_shows) = read.find_channel(tv_channel)?;
+            tv_shows
+                .iter()
+                .find(|show| url_id(&show.url) == tv_show)
+                .or_else(|| tv_shows.iter().find(|show| show.title == tv_show))
                 .cloned()
         }
 
+        /// Finds a channel by its id or its title, returns its title along with its shows.
+        pub async fn get_channel(&self, tv_channel: &str) -> Option<(String, Vec<TvShow>)> {
+            let read = self.state.read().await;
+            let (title, tv_shows) = read.find_channel(tv_channel)?;
+            Some((title.to_owned(), tv_shows.clone()))
+        }
+
+        /// Remembers the shows found by the site search, so that their episodes can be opened.
+        pub async fn add_found_shows(&self, tv_shows: &[TvShow]) -> anyhow::Result<()> {
+            let mut write = self.state.write().await;
+            for tv_show in tv_shows {
+                write.found_shows.retain(|show| show.title != tv_show.title);
+                write.found_shows.push(tv_show.clone());
+            }
+            let excess = write.found_shows.len().saturating_sub(MAX_FOUND_SHOWS);
+            write.found_shows.drain(..excess);
+            drop(write);
+            self.dump().await
+        }
+
+        /// Rewrites the urls of the cached tv shows, e.g. when the source site has moved.
+        pub async fn rebase_urls(&self, rebase: impl Fn(&str) -> String) -> anyhow::Result<()> {
+            let mut write = self.state.write().await;
+            let state = &mut *write;
+            let tv_shows = state.channels.iter_mut().flat_map(|(_, shows)| shows);
+            for tv_show in tv_shows.chain(&mut state.found_shows) {
+                tv_show.url = rebase(&tv_show.url);
+                tv_show.icon = rebase(&tv_show.icon);
+            }
+            drop(write);
+            self.rebuild_search_index().await;
+            self.dump().await
+        }
+
         pub async fn update_state(
             &self,
             new_channels: impl Iterator<Item = (&String, &Vec<TvShow>)>,
         ) -> anyhow::Result<()> {
-            let mut write = self.0.write().await;
+            let mut write = self.state.write().await;
             write.channels.clear();
             for (key, value) in new_channels {
                 write.channels.insert(key.to_owned(), value.to_owned());
             }
-            write.expires_at = expiry_time() + EXPIRY;
+            write.expires_at = expiry_time() + self.expiry;
             drop(write);
+            self.rebuild_search_index().await;
             self.dump().await
         }
 
+        pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+            self.search_index.read().unwrap().search(query, limit)
+        }
+
+        async fn rebuild_search_index(&self) {
+            let index = SearchIndex::build(&self.state.read().await.channels);
+            debug!("Rebuilt the search index with {} tv shows", index.len());
+            *self.search_index.write().unwrap() = index;
+        }
+
         async fn dump(&self) -> anyhow::Result<()> {
-            let content = serde_json::to_string_pretty(&*self.0.read().await)?;
-            let file = PathBuf::from(cache_folder()).join(TV_CHANNEL_FILE);
-            if !file.exists() {
-                let parent = file
-                    .parent()
-                    .ok_or_else(|| anyhow!("ohh man, can't even read cache folder"))?;
-                if !parent.exists() {
-                    fs::create_dir_all(parent).await?;
-                }
-            }
-            fs::write(file, content).await?;
-            Ok(())
+            let state = self.state.read().await.clone();
+            self.store.put(CHANNELS, CHANNELS_KEY, &state).await
         }
     }
+}
 
-    pub async fn init() {
-        if STATE.get().is_none() {
-            let file = PathBuf::from(cache_folder()).join(TV_CHANNEL_FILE);
-            let mut file_read_error = false;
-            let tv_channels = fs::read_to_string(&file)
-                .await
-                .and_then(|content| {
-                    serde_json::from_str::<TvChannelState>(&content)
-                        .map_err(|_| std::io::ErrorKind::InvalidData.into())
-                })
-                .unwrap_or_else(|e| {
-                    warn!("Couldn't deserialize {file:?}: {e}");
-                    file_read_error = true;
-                    TvChannelState {
-                        channels: LinkedHashMap::new(),
-                        expires_at: SystemTime::now(),
-                    }
-                });
-            if file_read_error && file.exists() {
-                fs::remove_file(&file).await.ok();
-            }
-            STATE
-                .set(TvChannelStateWrapper(RwLock::new(tv_channels)))
-                .map_err(|_| error!("Duh, couldn't set the state once_cell"))
-                .ok();
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use axum::body::HttpBody;
+    use axum::extract::State;
+    use axum::response::IntoResponse;
+    use linked_hash_map::LinkedHashMap;
+    use serde_json::json;
+
+    use crate::app_state::AppState;
+    use crate::config::Config;
+    use crate::error::ServerError;
+    use crate::fetcher::FixtureMode;
+    use crate::models::TvShow;
+    use crate::profiles::{CurrentProfile, DEFAULT_PROFILE};
+    use crate::utils::{TestDir, TV_CHANNEL_FILE};
+
+    use super::channel_home;
+
+    #[tokio::test]
+    async fn test_channel_home_from_cache() {
+        let cache_dir = TestDir::new("channel_home");
+        let state = json!({
+            "channels": {
+                "Star Plus": [{ "title": "Anupamaa", "url": "https://example.com/anupamaa/", "icon": "/media?url=icon" }],
+            },
+            "expires_at": SystemTime::now() + Duration::from_secs(60 * 60),
+        });
+        std::fs::write(cache_dir.join(TV_CHANNEL_FILE), state.to_string()).unwrap();
+
+        let state = AppState::init(&cache_dir, Config::default()).await.unwrap();
+        let profile = state.profiles.get(DEFAULT_PROFILE).await.unwrap();
+        let response = channel_home(State(state), CurrentProfile(profile))
+            .await
+            .ok()
+            .unwrap()
+            .into_response();
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend(chunk.unwrap());
         }
+        let body = serde_json::from_slice::<serde_json::Value>(&bytes).unwrap();
+        assert_eq!(body["Star Plus"][0]["title"], "Anupamaa");
+    }
+
+    #[tokio::test]
+    async fn test_expired_channels_are_served() {
+        let cache_dir = TestDir::new("expired_channels");
+        let state = json!({
+            "channels": {
+                "Star Plus": [{ "title": "Anupamaa", "url": "https://example.com/anupamaa/", "icon": "/media?url=icon" }],
+            },
+            "expires_at": SystemTime::now() - Duration::from_secs(60),
+        });
+        std::fs::write(cache_dir.join(TV_CHANNEL_FILE), state.to_string()).unwrap();
+
+        // Nothing can be downloaded, so the background refresh fails.
+        let config = Config {
+            fixture_mode: FixtureMode::Replay,
+            fixtures_dir: cache_dir.join("fixtures"),
+            ..Config::default()
+        };
+        let state = AppState::init(&cache_dir, config).await.unwrap();
+        let channels = super::tv_channels(&state).await.unwrap();
+        assert_eq!(channels["Star Plus"][0].title, "Anupamaa");
+
+        while state.tv_channels.is_refreshing() {
+            tokio::task::yield_now().await;
+        }
+        assert!(state.tv_channels.refresh_status().last_failure.is_some());
+        let channels = super::tv_channels(&state).await.unwrap();
+        assert_eq!(channels["Star Plus"].len(), 1);
+
+        // A forced refresh doesn't run next to another one.
+        assert!(state.tv_channels.try_begin_refresh());
+        let error = super::refresh_tv_channels(&state).await.unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(ServerError::Busy(_))));
+        state.tv_channels.end_refresh();
+    }
+
+    #[tokio::test]
+    async fn test_cold_start_waits_on_the_running_refresh() {
+        let cache_dir = TestDir::new("cold_start");
+        let state = AppState::init(&cache_dir, Config::default()).await.unwrap();
+        assert!(state.tv_channels.begin_refresh());
+        let waiting = tokio::spawn({
+            let state = state.clone();
+            async move { super::tv_channels(&state).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        let mut channels = LinkedHashMap::new();
+        channels.insert(
+            "Star Plus".to_owned(),
+            vec![TvShow {
+                title: "Anupamaa".into(),
+                url: "https://example.com/anupamaa/".into(),
+                icon: "icon".into(),
+            }],
+        );
+        state
+            .tv_channels
+            .update_state(channels.iter())
+            .await
+            .unwrap();
+        state.tv_channels.end_refresh();
+        let channels = waiting.await.unwrap().unwrap();
+        assert_eq!(channels["Star Plus"][0].title, "Anupamaa");
     }
 }
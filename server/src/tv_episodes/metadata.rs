@@ -1,41 +1,58 @@
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 use anyhow::anyhow;
 use reqwest::header;
 use tokio::fs;
 use tracing::*;
 
-use crate::http_util::{http_client, normalize_url};
+use crate::app_state::AppState;
+use crate::error::ServerError;
+use crate::http_util::normalize_url;
 use crate::models::VideoProvider;
-use crate::tv_channels::DESI_TV;
+use crate::profiles::Quality;
 use crate::tv_episodes::providers::{dailymotion, flash_player, speed, tv_logy};
-use crate::utils::{cache_folder, encode_uri_component, hash};
+use crate::utils::{encode_uri_component, hash};
 
 const METADATA_FILE: &str = "metadata.m3u8";
 
+/// Name of the metadata file of a quality, the best one keeps the name it always had.
+fn metadata_file_name(quality: Quality) -> String {
+    match quality.max_height() {
+        Some(height) => format!("metadata_{height}p.m3u8"),
+        None => METADATA_FILE.to_owned(),
+    }
+}
+
 impl VideoProvider {
-    pub async fn fetch_metadata(&self, link: &str) -> anyhow::Result<String> {
+    pub async fn fetch_metadata(
+        &self,
+        state: &AppState,
+        link: &str,
+        quality: Quality,
+    ) -> anyhow::Result<String> {
         debug!("Loading metadata of {self:?}:{link}");
+        let fetcher = &state.fetcher;
         let hsh = hash(link);
-        let metadata_file = PathBuf::from(cache_folder()).join(&hsh).join(METADATA_FILE);
+        let metadata_file = state
+            .cache_folder
+            .join(&hsh)
+            .join(metadata_file_name(quality));
         if !self.is_mp4() && metadata_file.exists() {
             return metadata_url(&metadata_file);
         }
         debug!("{metadata_file:?} doesn't exist");
-        let html = http_client()
+        let html = fetcher
             .get(link)
-            .header(header::REFERER, DESI_TV)
-            .send()
-            .await?
+            .header(header::REFERER, state.mirror.active())
             .text()
             .await?;
         let (m3u8_url, referer) = match self {
-            VideoProvider::TVLogy => tv_logy::find_m3u8(&html, link).await?,
-            VideoProvider::FlashPlayer => flash_player::find_m3u8(&html, link).await?,
-            VideoProvider::DailyMotion => dailymotion::find_m3u8(&html, link).await?,
-            VideoProvider::NetflixPlayer => dailymotion::find_m3u8(&html, link).await?,
-            VideoProvider::Speed => speed::find_mp4(&html, link).await?,
-            VideoProvider::Vkprime => speed::find_mp4(&html, link).await?,
+            VideoProvider::TVLogy => tv_logy::find_m3u8(fetcher, &html, link).await?,
+            VideoProvider::FlashPlayer => flash_player::find_m3u8(fetcher, &html, link).await?,
+            VideoProvider::DailyMotion => dailymotion::find_m3u8(fetcher, &html, link).await?,
+            VideoProvider::NetflixPlayer => dailymotion::find_m3u8(fetcher, &html, link).await?,
+            VideoProvider::Speed => speed::find_mp4(fetcher, &html, link).await?,
+            VideoProvider::Vkprime => speed::find_mp4(fetcher, &html, link).await?,
             // provider => return Err(anyhow::anyhow!("{provider:?} not implemented")),
         };
         if self.is_mp4() {
@@ -45,21 +62,17 @@ impl VideoProvider {
             Ok(url)
         } else {
             info!("Found M3U8 url: {m3u8_url} with referer: {referer}");
-            let m3u8_content = http_client()
+            let m3u8_content = fetcher
                 .get(&m3u8_url)
                 .header(header::REFERER, &referer)
-                .send()
-                .await?
                 .text()
                 .await?;
-            let video_url = find_best_video_url(&m3u8_content, &m3u8_url)?;
+            let video_url = find_best_video_url(&m3u8_content, &m3u8_url, quality.max_height())?;
             info!("Found video url: {video_url}");
 
-            let m3u8_content = http_client()
+            let m3u8_content = fetcher
                 .get(&video_url)
                 .header(header::REFERER, &referer)
-                .send()
-                .await?
                 .text()
                 .await?;
             let m3u8_content = convert_m3u8(&m3u8_content, &video_url, &referer, &hsh)?;
@@ -82,16 +95,66 @@ impl VideoProvider {
     }
 }
 
-fn find_best_video_url(m3u8: &str, host_url: &str) -> anyhow::Result<String> {
-    let mut itr = m3u8.split('\n').rev().peekable();
-    while let Some(cur) = itr.next() {
-        if let Some(next) = itr.peek() {
-            if next.starts_with("#EXT-X-STREAM-INF") {
-                return Ok(normalize_url(cur, host_url)?.into_owned());
+/// Deletes the cached metadata files (of every quality) of an episode part, returns false if
+/// none was cached.
+pub async fn invalidate_metadata(state: &AppState, hash: &str) -> anyhow::Result<bool> {
+    // The hash is a part of the path, anything but hex digits could escape the cache folder.
+    if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ServerError::InvalidInput(format!("Invalid episode hash: '{hash}'")).into());
+    }
+    let folder = state.cache_folder.join(hash);
+    let mut removed = false;
+    for quality in [Quality::Best, Quality::High, Quality::Medium, Quality::Low] {
+        let metadata_file = folder.join(metadata_file_name(quality));
+        if metadata_file.exists() {
+            info!("Deleting {metadata_file:?}");
+            fs::remove_file(&metadata_file).await?;
+            removed = true;
+        }
+    }
+    Ok(removed)
+}
+
+/// Picks the tallest variant which isn't taller than `max_height`, or the shortest one if
+/// they're all taller. Without a `max_height` the last variant is picked, as the playlists
+/// list them from the worst to the best.
+fn find_best_video_url(
+    m3u8: &str,
+    host_url: &str,
+    max_height: Option<u32>,
+) -> anyhow::Result<String> {
+    let mut variants = Vec::new();
+    let mut itr = m3u8.split('\n').peekable();
+    while let Some(line) = itr.next() {
+        if line.starts_with("#EXT-X-STREAM-INF") {
+            if let Some(url) = itr.peek().filter(|url| !url.trim().is_empty()) {
+                variants.push((variant_height(line), url.trim()));
             }
         }
     }
-    Err(anyhow!("Couldn't parse M3U8 content: '{m3u8}'"))
+    let best = match max_height {
+        None => variants.last(),
+        Some(max_height) => variants
+            .iter()
+            .filter(|(height, _)| height.is_some_and(|h| h <= max_height))
+            .max_by_key(|(height, _)| *height)
+            .or_else(|| {
+                let tall = variants.iter().filter(|(height, _)| height.is_some());
+                tall.min_by_key(|(height, _)| *height)
+            })
+            .or(variants.last()),
+    };
+    match best {
+        Some((_, url)) => Ok(normalize_url(url, host_url)?.into_owned()),
+        None => Err(ServerError::Parse(format!("Couldn't parse M3U8 content: '{m3u8}'")).into()),
+    }
+}
+
+/// Height of the `RESOLUTION=1280x720` attribute of a variant.
+fn variant_height(stream_inf: &str) -> Option<u32> {
+    let resolution = stream_inf.split("RESOLUTION=").nth(1)?;
+    let resolution = resolution.split(',').next()?;
+    resolution.split('x').nth(1)?.trim().parse().ok()
 }
 
 fn convert_m3u8(m3u8: &str, host_url: &str, referer: &str, hash: &str) -> anyhow::Result<String> {
@@ -127,3 +190,43 @@ fn metadata_url(path: &Path) -> anyhow::Result<String> {
         file_name.to_string_lossy()
     ))
 }
+
+#[cfg(test)]
+mod test {
+    use crate::app_state::AppState;
+    use crate::config::Config;
+    use crate::utils::TestDir;
+
+    use super::{find_best_video_url, invalidate_metadata, METADATA_FILE};
+
+    #[tokio::test]
+    async fn test_invalidate_metadata() {
+        let cache_dir = TestDir::new("invalidate_metadata");
+        std::fs::create_dir_all(cache_dir.join("abc123")).unwrap();
+        std::fs::write(cache_dir.join("abc123").join(METADATA_FILE), "#EXTM3U").unwrap();
+        let state = AppState::init(&cache_dir, Config::default()).await.unwrap();
+
+        assert!(invalidate_metadata(&state, "abc123").await.unwrap());
+        assert!(!invalidate_metadata(&state, "abc123").await.unwrap());
+        assert!(invalidate_metadata(&state, "../abc123").await.is_err());
+    }
+
+    #[test]
+    fn test_find_best_video_url() {
+        let m3u8 = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360
+360.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1400000,RESOLUTION=1280x720
+720.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2800000,RESOLUTION=1920x1080
+1080.m3u8
+";
+        let host = "https://example.com/master.m3u8";
+        let best = |max_height| find_best_video_url(m3u8, host, max_height).unwrap();
+        assert_eq!(best(None), "https://example.com/1080.m3u8");
+        assert_eq!(best(Some(720)), "https://example.com/720.m3u8");
+        assert_eq!(best(Some(480)), "https://example.com/360.m3u8");
+        assert_eq!(best(Some(240)), "https://example.com/360.m3u8");
+        assert!(find_best_video_url("#EXTM3U", host, None).is_err());
+    }
+}
@@ -1,37 +1,54 @@
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
 use std::time::Instant;
 
-use anyhow::{anyhow, Context};
-use axum::extract::Path;
+use anyhow::Context;
+use axum::extract::{Path, State};
 use axum::response::IntoResponse;
 use axum::Json;
 use futures::{stream, StreamExt, TryStreamExt};
 use tracing::*;
 
-use crate::error::HttpError;
+use crate::app_state::AppState;
+use crate::error::{path_param, show_path, HttpError, ServerError};
+use crate::metrics::{record_resolution, time_step};
 use crate::models::Episode;
+use crate::profiles::{CurrentProfile, Quality};
 use crate::tv_shows::get_episode_parts;
-use crate::utils::cache_folder;
+
+pub use metadata::invalidate_metadata;
 
 mod metadata;
 mod providers;
 
 pub async fn episode_parts(
+    State(state): State<AppState>,
+    CurrentProfile(profile): CurrentProfile,
     Path(params): Path<HashMap<String, String>>,
 ) -> Result<impl IntoResponse, HttpError> {
+    let (tv_channel, tv_show) = show_path(&params)?;
+    let episode = path_param(&params, "episode")?;
+    Ok(Json(
+        resolve_episode(&state, tv_channel, tv_show, episode, profile.quality).await?,
+    ))
+}
+
+/// Resolves the playable urls of every part of an episode, trying one provider after another.
+pub async fn resolve_episode(
+    state: &AppState,
+    tv_channel: &str,
+    tv_show: &str,
+    episode: &str,
+    quality: Quality,
+) -> anyhow::Result<Vec<(String, String)>> {
     let start = Instant::now();
-    let tv_channel = params
-        .get("tv_channel")
-        .ok_or_else(|| anyhow!("No tv channel"))?;
-    let tv_show = params.get("tv_show").ok_or_else(|| anyhow!("No tv show"))?;
-    let episode = params.get("episode").ok_or_else(|| anyhow!("No episode"))?;
     info!("Loading parts for {tv_channel} > {tv_show} > {episode}");
-    let episode_parts = get_episode_parts(tv_channel, tv_show, episode)
+    let episode_parts = get_episode_parts(state, tv_channel, tv_show, episode)
         .await
         .ok_or_else(|| {
-            anyhow!("Couldn't find TvEpisodes with {tv_channel} > {tv_show} > {episode}")
+            ServerError::EpisodeNotFound(format!(
+                "Couldn't find TvEpisodes with {tv_channel} > {tv_show} > {episode}"
+            ))
         })?;
 
     let mut episode_error = None;
@@ -39,9 +56,14 @@ pub async fn episode_parts(
         let parts_num = links.len();
         let metadata_result = stream::iter(links)
             .map(|(title, link)| async move {
-                provider
-                    .fetch_metadata(&link)
-                    .await
+                let metadata = time_step(
+                    "fetch_metadata",
+                    Some(provider),
+                    provider.fetch_metadata(state, &link, quality),
+                )
+                .await;
+                record_resolution(provider, metadata.is_ok());
+                metadata
                     .with_context(|| format!("'{title}': {provider:?} => {link}"))
                     .map(|meta_url| (title, meta_url))
             })
@@ -55,7 +77,7 @@ pub async fn episode_parts(
                     provider,
                     start.elapsed()
                 );
-                return Ok(Json(result));
+                return Ok(result);
             }
             Err(e) => {
                 warn!("Failed to load episode parts: {e:?}");
@@ -68,21 +90,18 @@ pub async fn episode_parts(
         start.elapsed()
     );
     let error = episode_error
-        .map(|e| anyhow!("Failed to load {tv_channel} > {tv_show} > {episode}: {e:?}"))
-        .unwrap_or_else(|| anyhow!("Failed to load {tv_channel} > {tv_show} > {episode}"));
-    Err(error.into())
+        .map(|e| format!("Failed to load {tv_channel} > {tv_show} > {episode}: {e:?}"))
+        .unwrap_or_else(|| format!("Failed to load {tv_channel} > {tv_show} > {episode}"));
+    Err(ServerError::ProviderResolution(error).into())
 }
 
 pub async fn get_metadata(
+    State(state): State<AppState>,
     Path(params): Path<HashMap<String, String>>,
 ) -> Result<impl IntoResponse, HttpError> {
-    let folder = params
-        .get("folder")
-        .ok_or_else(|| anyhow!("Folder not present in url"))?;
-    let file_name = params
-        .get("file_name")
-        .ok_or_else(|| anyhow!("File name not present in url"))?;
-    let file = PathBuf::from(cache_folder()).join(folder).join(file_name);
+    let folder = path_param(&params, "folder")?;
+    let file_name = path_param(&params, "file_name")?;
+    let file = state.cache_folder.join(folder).join(file_name);
     info!("Reading metadata from {file:?}");
     Ok(fs::read_to_string(file).map_err(anyhow::Error::from)?)
 }
@@ -4,12 +4,17 @@ use serde::Deserialize;
 use tokio::time::Instant;
 use tracing::*;
 
-use crate::http_util::{find_host, http_client};
+use crate::fetcher::Fetcher;
+use crate::http_util::find_host;
 use crate::tv_episodes::providers::flash_player::find_source;
 
 use super::find_iframe;
 
-pub async fn find_m3u8(html: &str, referer: &str) -> anyhow::Result<(String, String)> {
+pub async fn find_m3u8(
+    fetcher: &Fetcher,
+    html: &str,
+    referer: &str,
+) -> anyhow::Result<(String, String)> {
     #[derive(Deserialize, Debug)]
     struct Source {
         src: String,
@@ -18,11 +23,9 @@ pub async fn find_m3u8(html: &str, referer: &str) -> anyhow::Result<(String, Str
     let start = Instant::now();
     let iframe_src = find_iframe(html, referer)?;
     debug!("Got iframe src: {iframe_src}");
-    let html = http_client()
+    let html = fetcher
         .get(&iframe_src)
         .header(header::REFERER, find_host(referer)?)
-        .send()
-        .await?
         .text()
         .await?;
     let vid_src =
@@ -4,20 +4,23 @@ use reqwest::header;
 use tokio::time::Instant;
 use tracing::*;
 
-use crate::http_util::{find_host, http_client, normalize_url};
+use crate::fetcher::Fetcher;
+use crate::http_util::{find_host, normalize_url};
 use crate::tv_episodes::providers::tv_logy::find_eval;
 
 use super::find_iframe;
 
-pub async fn find_mp4(html: &str, referer: &str) -> anyhow::Result<(String, String)> {
+pub async fn find_mp4(
+    fetcher: &Fetcher,
+    html: &str,
+    referer: &str,
+) -> anyhow::Result<(String, String)> {
     let start = Instant::now();
     let iframe_src = find_iframe(html, referer)?;
     debug!("Got iframe src: {iframe_src}");
-    let html = http_client()
+    let html = fetcher
         .get(&iframe_src)
         .header(header::REFERER, find_host(referer)?)
-        .send()
-        .await?
         .text()
         .await?;
     let eval_src = find_eval(&html).ok_or_else(|| anyhow!("Couldn't find eval script"))?;
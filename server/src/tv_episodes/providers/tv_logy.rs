@@ -2,34 +2,39 @@ use anyhow::anyhow;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 use quick_js::{console, Context};
-use reqwest::header;
+use reqwest::header::{self, HeaderName};
 use serde::Deserialize;
 use tokio::time::Instant;
 use tracing::*;
 
-use crate::http_util::{find_host, http_client, normalize_url};
+use crate::fetcher::Fetcher;
+use crate::http_util::{find_host, normalize_url};
 
 use super::find_iframe;
 
-pub async fn find_m3u8(html: &str, referer: &str) -> anyhow::Result<(String, String)> {
+pub async fn find_m3u8(
+    fetcher: &Fetcher,
+    html: &str,
+    referer: &str,
+) -> anyhow::Result<(String, String)> {
     let start = Instant::now();
     let iframe_src = find_iframe(html, referer)?;
     debug!("Got iframe src: {iframe_src}");
-    let m3u8_url = match tv_logy_v2(&iframe_src).await {
+    let m3u8_url = match tv_logy_v2(fetcher, &iframe_src).await {
         Ok(m3u8_url) => {
             info!("Successfully resolved m3u8 url via tv_logy v2");
             m3u8_url
         }
         Err(e) => {
             warn!("Failed to resolve m3u8 url via v2 {e:?}");
-            tv_logy_v1(&iframe_src, referer).await?
+            tv_logy_v1(fetcher, &iframe_src, referer).await?
         }
     };
     info!("Time taken to resolve TVLogy: {:?}", start.elapsed());
     Ok((m3u8_url, iframe_src))
 }
 
-async fn tv_logy_v2(iframe_src: &str) -> anyhow::Result<String> {
+async fn tv_logy_v2(fetcher: &Fetcher, iframe_src: &str) -> anyhow::Result<String> {
     #[derive(Deserialize)]
     struct VideoSrc {
         #[serde(rename(deserialize = "videoSource"))]
@@ -37,24 +42,23 @@ async fn tv_logy_v2(iframe_src: &str) -> anyhow::Result<String> {
     }
 
     let video_src = format!("{iframe_src}&do=getVideo");
-    let json = http_client()
+    let json = fetcher
         .post(&video_src)
         .header(header::REFERER, iframe_src)
-        .header("X-Requested-With", "XMLHttpRequest")
-        .send()
-        .await?
+        .header(
+            HeaderName::from_static("x-requested-with"),
+            "XMLHttpRequest",
+        )
         .text()
         .await?;
     let video_src = serde_json::from_str::<VideoSrc>(&json)?;
     Ok(video_src.video_src)
 }
 
-async fn tv_logy_v1(iframe_src: &str, referer: &str) -> anyhow::Result<String> {
-    let html = http_client()
+async fn tv_logy_v1(fetcher: &Fetcher, iframe_src: &str, referer: &str) -> anyhow::Result<String> {
+    let html = fetcher
         .get(iframe_src)
         .header(header::REFERER, find_host(referer)?)
-        .send()
-        .await?
         .text()
         .await?;
     let eval_src = find_eval(&html).ok_or_else(|| anyhow!("Couldn't find eval script"))?;
@@ -1,93 +1,120 @@
-use std::collections::HashMap;
-use std::time::Instant;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::anyhow;
-use axum::extract::{Path, Query};
+use axum::extract::{Path, Query, State};
 use axum::response::IntoResponse;
 use axum::Json;
 use futures::{stream, StreamExt};
-use once_cell::sync::OnceCell;
-use reqwest::header;
-use scraper::{ElementRef, Html};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::sync::oneshot;
 use tokio::sync::oneshot::Sender;
 use tracing::*;
 
-use crate::error::HttpError;
-use crate::http_util::{find_host, http_client, normalize_url, s, PARALLELISM};
-use crate::models::{Episode, TvShow, TvShowEpisodes, VideoProvider};
-use crate::tv_channels::get_tv_show;
-use crate::utils::fix_title;
+use crate::app_state::AppState;
+use crate::config::Config;
+use crate::error::{query_param, show_path, HttpError, ServerError};
+use crate::metrics::time_step;
+use crate::models::{
+    Episode, EpisodeInfo, OpenedShow, TvEpisode, TvShow, TvShowEpisodes, VideoProvider,
+};
+use crate::sources::{ContentSource, EpisodePage};
+use crate::utils::url_id;
 
-static SENDER: OnceCell<UnboundedSender<(TvShow, Sender<TvShowEpisodes>)>> = OnceCell::new();
+pub use state::TvShowsStateWrapper;
+
+/// A tv show, whether the next page of its episodes is wanted, and where to send the episodes.
+pub type TvShowRequest = (TvShow, bool, Sender<TvShowEpisodes>);
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-struct TvShowResponse {
+pub struct TvShowResponse {
+    id: String,
     episodes: Vec<String>,
+    /// Same order as `episodes`, with the air dates & numbers parsed out of the titles.
+    episode_info: Vec<EpisodeInfo>,
     has_more: bool,
 }
 
 impl TvShowEpisodes {
-    fn to_res(&self) -> TvShowResponse {
-        let episodes = self.episodes.iter().map(|(eps, _)| eps).cloned().collect();
-        let has_more = self.last_page > self.cur_page;
-        TvShowResponse { episodes, has_more }
+    fn to_res(&self, show: &TvShow) -> TvShowResponse {
+        let episodes = self.episodes.iter().map(|e| e.title.clone()).collect();
+        let episode_info = self
+            .episodes
+            .iter()
+            .map(|episode| EpisodeInfo::parse(&show.title, episode))
+            .collect();
+        let has_more = self.has_more();
+        TvShowResponse {
+            id: url_id(&show.url),
+            episodes,
+            episode_info,
+            has_more,
+        }
     }
 }
 
-pub async fn init_tv_shows() {
-    state::init().await;
-
+/// Spawns the queue which loads the episodes of one tv show at a time.
+pub fn start_tv_shows_processor(
+    tv_shows: Arc<TvShowsStateWrapper>,
+    source: Arc<dyn ContentSource>,
+    config: Arc<Config>,
+) -> UnboundedSender<TvShowRequest> {
     let (sender, mut receiver) = unbounded_channel();
-    SENDER.set(sender).unwrap();
-    process(&mut receiver).await;
+    tokio::spawn(async move { process(&mut receiver, &tv_shows, source.as_ref(), &config).await });
+    sender
 }
 
-async fn process(receiver: &mut UnboundedReceiver<(TvShow, Sender<TvShowEpisodes>)>) {
+async fn process(
+    receiver: &mut UnboundedReceiver<TvShowRequest>,
+    tv_shows_state: &TvShowsStateWrapper,
+    source: &dyn ContentSource,
+    config: &Config,
+) {
     let mut stack = Vec::new();
     while let Some(req) = receiver.recv().await {
         stack.push(req);
         while let Ok(req) = receiver.try_recv() {
             stack.push(req);
         }
-        while let Some((soap, sender)) = stack.pop() {
+        while let Some((soap, load_more, sender)) = stack.pop() {
             info!("Processing {soap:?}");
-            let key = format!("{}:{}", soap.title, soap.url);
-            let tv_shows = state::STATE.get().unwrap().get_tv_show(&key).await;
-            let soap_url = if let Some(tv_shows) = tv_shows {
-                if tv_shows.cur_page == tv_shows.last_page {
-                    info!(
-                        "All episodes of '{}' has been downloaded already",
-                        soap.title
-                    );
-                    sender.send(tv_shows).ok();
-                    continue;
-                } else {
-                    format!("{}page/{}/", soap.url, tv_shows.cur_page + 1)
+            let key = cache_key(&soap);
+            let cached = tv_shows_state.get_tv_show(&key).await;
+            let is_cached = cached.is_some();
+            let mut tv_show_episodes = cached.unwrap_or_default();
+            let mut changed = false;
+            if is_cached && tv_show_episodes.is_stale(config.show_refresh()) {
+                info!("Checking '{}' for new episodes", soap.title);
+                match refresh(source, config, &soap.url, &mut tv_show_episodes).await {
+                    Ok(()) => changed = true,
+                    Err(e) => warn!("Failed to refresh {}: {e:?}", soap.url),
                 }
+            }
+            if is_cached && !load_more {
+                // Only a refresh was asked for.
+            } else if !tv_show_episodes.has_more() {
+                info!(
+                    "All episodes of '{}' has been downloaded already",
+                    soap.title
+                );
             } else {
-                soap.url.to_owned()
-            };
-            let mut tv_show_episodes = state::STATE
-                .get()
-                .unwrap()
-                .get_tv_show(&key)
-                .await
-                .unwrap_or_else(|| TvShowEpisodes {
-                    episodes: Vec::new(),
-                    cur_page: 1,
-                    last_page: 1,
-                });
-            info!("Loading episodes from {soap_url}");
-            if let Ok((new_episodes, cur_page, last_page)) = load_episodes(&soap_url).await {
-                tv_show_episodes.episodes.extend(new_episodes);
-                tv_show_episodes.cur_page = cur_page;
-                tv_show_episodes.last_page = last_page;
-                state::STATE
-                    .get()
-                    .unwrap()
+                match load_next_page(source, config, &soap.url, &mut tv_show_episodes).await {
+                    Ok(0) => changed = true,
+                    Ok(shift) => {
+                        changed = true;
+                        info!("Episodes of '{}' have shifted by {shift}", soap.title);
+                        if let Err(e) =
+                            refresh(source, config, &soap.url, &mut tv_show_episodes).await
+                        {
+                            warn!("Failed to refresh {}: {e:?}", soap.url);
+                        }
+                    }
+                    Err(e) => warn!("Failed to load the episodes of {}: {e:?}", soap.url),
+                }
+            }
+            if changed {
+                tv_shows_state
                     .put_tv_show(key, tv_show_episodes.clone())
                     .await;
             }
@@ -102,104 +129,283 @@ async fn process(receiver: &mut UnboundedReceiver<(TvShow, Sender<TvShowEpisodes
     }
 }
 
-pub async fn episodes(
-    Path(param): Path<HashMap<String, String>>,
-    Query(query_param): Query<HashMap<String, bool>>,
-) -> Result<impl IntoResponse, HttpError> {
-    if state::STATE.get().is_none() || SENDER.get().is_none() {
-        return Err(anyhow!("State is not initialized yet").into());
+/// Most pages read while looking for the episodes which were known before a refresh.
+const MAX_REFRESH_PAGES: usize = 3;
+
+/// Re-reads the first page(s) and puts the episodes published since the last refresh on the top.
+///
+/// The new episodes push the older ones down the pages, which is remembered as the shift
+/// of the episodes loaded so far, so that `load_more` continues from the right page.
+async fn refresh(
+    source: &dyn ContentSource,
+    config: &Config,
+    url: &str,
+    tv_show: &mut TvShowEpisodes,
+) -> anyhow::Result<()> {
+    let known = tv_show.known_ids();
+    let mut new_episodes = Vec::new();
+    let mut shift = 0;
+    for page in 1..=MAX_REFRESH_PAGES {
+        let batch = load_batch(source, config, url, page, &known).await?;
+        if page == 1 {
+            tv_show.adopt_page_size(batch.link_ids.len());
+        }
+        tv_show.last_page = batch.last_page;
+        let fresh = batch.link_ids.iter().take_while(|id| !known.contains(*id));
+        let fresh = fresh.collect::<HashSet<_>>();
+        shift += fresh.len();
+        new_episodes.extend(
+            batch
+                .episodes
+                .into_iter()
+                .filter(|episode| fresh.contains(&episode.id)),
+        );
+        if fresh.len() < batch.link_ids.len() || page >= batch.last_page {
+            info!(
+                "Found {} new episodes, {shift} in total",
+                new_episodes.len()
+            );
+            tv_show.episodes.splice(0..0, new_episodes);
+            tv_show.loaded_links += shift;
+            tv_show.total_links = tv_show.total_links.map(|total| total + shift);
+            tv_show.refreshed_at = Some(SystemTime::now());
+            return Ok(());
+        }
     }
+    // Too much has changed to tell where the known episodes are now, so they're started over.
+    warn!("Didn't find any known episode in the first {MAX_REFRESH_PAGES} pages of {url}");
+    tv_show.episodes = new_episodes;
+    tv_show.loaded_links = shift;
+    tv_show.total_links = None;
+    tv_show.cur_page = MAX_REFRESH_PAGES;
+    tv_show.refreshed_at = Some(SystemTime::now());
+    Ok(())
+}
+
+/// Loads the page after the loaded episodes, returns the number of episodes which have been
+/// published on the top since the last refresh, as they push the known ones into this page.
+async fn load_next_page(
+    source: &dyn ContentSource,
+    config: &Config,
+    url: &str,
+    tv_show: &mut TvShowEpisodes,
+) -> anyhow::Result<usize> {
+    let page = tv_show.next_page();
+    let known = tv_show.known_ids();
+    let batch = load_batch(source, config, url, page, &known).await?;
+    if page == 1 {
+        tv_show.adopt_page_size(batch.link_ids.len());
+        tv_show.refreshed_at = Some(SystemTime::now());
+    }
+    let start = (page - 1) * tv_show.page_size;
+    let expected_overlap = tv_show.loaded_links.saturating_sub(start);
+    let overlap = batch
+        .link_ids
+        .iter()
+        .take_while(|id| known.contains(*id))
+        .count();
+    let shift = overlap.saturating_sub(expected_overlap);
+    // The known episodes were skipped, so there are no duplicates whatever the shift is.
+    tv_show.episodes.extend(batch.episodes);
+    // The new episodes on the top aren't loaded yet, so they don't count.
+    let end = start + batch.link_ids.len() - shift;
+    tv_show.loaded_links = tv_show.loaded_links.max(end);
+    tv_show.total_links = (batch.cur_page >= batch.last_page).then_some(end);
+    tv_show.cur_page = batch.cur_page;
+    tv_show.last_page = batch.last_page;
+    Ok(shift)
+}
+
+async fn load_batch(
+    source: &dyn ContentSource,
+    config: &Config,
+    url: &str,
+    page: usize,
+    known: &HashSet<String>,
+) -> anyhow::Result<EpisodeBatch> {
+    info!("Loading page {page} of episodes from {url}");
+    time_step(
+        "load_episodes",
+        None,
+        load_episodes(source, config.parallelism, url, page, known),
+    )
+    .await
+}
 
+impl TvShowEpisodes {
+    fn is_stale(&self, max_age: Duration) -> bool {
+        self.refreshed_at
+            .and_then(|refreshed_at| refreshed_at.elapsed().ok())
+            .map(|age| age > max_age)
+            .unwrap_or(true)
+    }
+
+    fn known_ids(&self) -> HashSet<String> {
+        self.episodes.iter().map(|e| e.id.clone()).collect()
+    }
+
+    fn adopt_page_size(&mut self, page_size: usize) {
+        if self.page_size == 0 && page_size > 0 {
+            // Saved before the page size was tracked, when every page but the last was full.
+            self.loaded_links = self.loaded_links.max(self.cur_page * page_size);
+            self.page_size = page_size;
+        }
+    }
+
+    /// The page holding the first episode which hasn't been loaded yet.
+    fn next_page(&self) -> usize {
+        let page = match self.loaded_links.checked_div(self.page_size) {
+            Some(full_pages) => full_pages + 1,
+            None => self.cur_page + 1,
+        };
+        page.min(self.last_page.max(1))
+    }
+
+    fn has_more(&self) -> bool {
+        if self.refreshed_at.is_none() && self.episodes.is_empty() {
+            return true;
+        }
+        match self.total_links {
+            _ if self.page_size == 0 => self.last_page > self.cur_page,
+            Some(total_links) => self.loaded_links < total_links,
+            None => true,
+        }
+    }
+}
+
+pub async fn episodes(
+    State(state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+    Query(query_params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, HttpError> {
     let start = Instant::now();
-    let tv_channel = param
-        .get("tv_channel")
-        .ok_or_else(|| anyhow!("Path didn't contain TvChannel"))?;
-    let tv_show = param
-        .get("tv_show")
-        .ok_or_else(|| anyhow!("Path didn't contain TvShow"))?;
-    let &load_more = query_param.get("load_more").unwrap_or(&false);
+    let (tv_channel, tv_show) = show_path(&params)?;
+    // Other params, like the profile, are passed along too.
+    let load_more = query_param(&query_params, "load_more", false)?;
+    let response = tv_show_episodes(&state, tv_channel, tv_show, load_more).await?;
+    mark_opened(&state, tv_channel, tv_show).await;
+    info!("Time taken to serve episodes: {:?}", start.elapsed());
+    Ok(Json(response))
+}
+
+/// Returns the cached episodes of a tv show, loading them (or the next page of them) if needed.
+pub async fn tv_show_episodes(
+    state: &AppState,
+    tv_channel: &str,
+    tv_show: &str,
+    load_more: bool,
+) -> anyhow::Result<TvShowResponse> {
     info!("Fetching episodes for: {tv_channel} > {tv_show} ({load_more})");
-    let soap = get_tv_show(tv_channel, tv_show)
+    state.follow_mirror().await?;
+    let soap = state
+        .tv_channels
+        .get_tv_show(tv_channel, tv_show)
         .await
-        .ok_or_else(|| anyhow!("Couldn't find Soap with {tv_channel} & {tv_show}"))?;
+        .ok_or_else(|| {
+            ServerError::ShowNotFound(format!("Couldn't find Soap with {tv_channel} & {tv_show}"))
+        })?;
 
-    let key = format!("{}:{}", soap.title, soap.url);
-    let tv_show = state::STATE.get().unwrap().get_tv_show(&key).await;
+    let key = cache_key(&soap);
+    let tv_show = state.tv_shows.get_tv_show(&key).await;
     if let Some(tv_shows) = tv_show {
-        info!("Got unexpired TvShows from cache");
-        if !load_more {
-            return Ok(Json(tv_shows.to_res()));
+        if !load_more && !tv_shows.is_stale(state.config.show_refresh()) {
+            info!("Got fresh TvShows from cache");
+            return Ok(tv_shows.to_res(&soap));
         }
     }
 
     let (sender, receiver) = oneshot::channel();
-    SENDER
-        .get()
-        .unwrap()
-        .send((soap, sender))
-        .map_err(|_| anyhow!("Failed to enqueue the request"))?;
-    let response = receiver
+    let tv_show = soap.clone();
+    state
+        .tv_show_sender
+        .send((soap, load_more, sender))
+        .map_err(|_| ServerError::NotInitialized("Failed to enqueue the request".into()))?;
+    let response = receiver.await.map_err(|_| {
+        ServerError::NotInitialized("Failed to receive the response from download queue".into())
+    })?;
+    Ok(response.to_res(&tv_show))
+}
+
+/// Remembers that a tv show has been opened, so that the scheduler keeps it warm.
+async fn mark_opened(state: &AppState, tv_channel: &str, tv_show: &str) {
+    if let Some(soap) = state.tv_channels.get_tv_show(tv_channel, tv_show).await {
+        let key = cache_key(&soap);
+        let opened = OpenedShow {
+            channel: tv_channel.to_owned(),
+            tv_show: soap,
+            opened_at: SystemTime::now(),
+        };
+        state.tv_shows.mark_opened(&key, opened).await;
+    }
+}
+
+/// Drops the cached episodes of a tv show, returns false if none were cached.
+pub async fn invalidate_tv_show(
+    state: &AppState,
+    tv_channel: &str,
+    tv_show: &str,
+) -> anyhow::Result<bool> {
+    let soap = state
+        .tv_channels
+        .get_tv_show(tv_channel, tv_show)
         .await
-        .map_err(|_| anyhow!("Failed to receive the response from download queue"))?;
-    info!("Time taken to serve episodes: {:?}", start.elapsed());
-    Ok(Json(response.to_res()))
+        .ok_or_else(|| {
+            ServerError::ShowNotFound(format!("Couldn't find Soap with {tv_channel} & {tv_show}"))
+        })?;
+    Ok(state.tv_shows.remove_tv_show(&cache_key(&soap)).await)
+}
+
+fn cache_key(soap: &TvShow) -> String {
+    format!("{}:{}", soap.title, soap.url)
+}
+
+/// Episodes of one page, the known ones are skipped.
+struct EpisodeBatch {
+    episodes: Vec<TvEpisode>,
+    /// Ids of every episode link on the page, in the order of the page.
+    link_ids: Vec<String>,
+    cur_page: usize,
+    last_page: usize,
 }
 
 async fn load_episodes(
+    source: &dyn ContentSource,
+    parallelism: usize,
     tv_show_url: &str,
-) -> anyhow::Result<(Vec<(String, Vec<Episode>)>, usize, usize)> {
-    fn find_episode_links(html: &str, host: &str) -> (Vec<String>, usize, usize) {
-        let doc = Html::parse_document(html);
-        let links = doc
-            .select(&s(
-                "#content_box .latestPost .latestPost-content h2.title a",
-            ))
-            .filter_map(|e| e.value().attr("href"))
-            .filter_map(|href| normalize_url(href, host).ok())
-            .map(|href| href.into_owned())
-            .collect::<Vec<_>>();
-        let current_page = doc
-            .select(&s(".nav-links .page-numbers.current"))
-            .next()
-            .map(|li| li.inner_html())
-            .and_then(|p| p.parse::<usize>().ok())
-            .unwrap_or(1);
-        let last_page = doc
-            .select(&s(".nav-links .page-numbers:not(.next)"))
-            .last()
-            .map(|li| li.inner_html())
-            .and_then(|p| p.parse::<usize>().ok())
-            .unwrap_or(current_page);
-        (links, current_page, last_page)
-    }
-
-    let response = http_client()
-        .get(tv_show_url)
-        .header(header::REFERER, find_host(tv_show_url)?)
-        .send()
-        .await?
-        .text()
-        .await?;
-    let (links, cur_page, last_page) = find_episode_links(&response, tv_show_url);
+    page: usize,
+    known: &HashSet<String>,
+) -> anyhow::Result<EpisodeBatch> {
+    let EpisodePage {
+        url,
+        links,
+        cur_page,
+        last_page,
+    } = source.list_episodes(tv_show_url, page).await?;
+    let link_ids = links.iter().map(|link| url_id(link)).collect::<Vec<_>>();
+    let links = links
+        .into_iter()
+        .filter(|link| !known.contains(&url_id(link)))
+        .collect::<Vec<_>>();
     info!("Searching for TvShow parts in {links:#?}");
+    let url = &url;
     let episodes = stream::iter(links)
         .map(|link| async move {
-            match load_episodes_video_links(&link, tv_show_url).await {
-                Ok(res) => Some(res),
+            match source.list_episode_links(&link, url).await {
+                Ok((name, eps)) => Some((url_id(&link), name, eps)),
                 Err(e) => {
                     warn!("Failed to load episodes from {link}: {e}");
                     None
                 }
             }
         })
-        .buffered(PARALLELISM)
+        .buffered(parallelism)
         .collect::<Vec<_>>()
         .await;
     let mut map = HashMap::with_capacity(episodes.len());
     let filtered_episodes = episodes
         .into_iter()
         .flatten()
-        .filter_map(|(name, eps)| {
+        .filter_map(|(id, name, eps)| {
             if eps.is_empty() {
                 return None;
             }
@@ -210,71 +416,65 @@ async fn load_episodes(
                 map.insert(name.clone(), 1);
                 name
             };
-            Some((new_name, eps))
+            Some(TvEpisode {
+                id,
+                title: new_name,
+                parts: eps,
+            })
         })
         .collect::<Vec<_>>();
-    Ok((filtered_episodes, cur_page, last_page))
+    Ok(EpisodeBatch {
+        episodes: filtered_episodes,
+        link_ids,
+        cur_page,
+        last_page,
+    })
 }
 
-async fn load_episodes_video_links(
-    eps_url: &str,
-    referer: &str,
-) -> anyhow::Result<(String, Vec<Episode>)> {
-    fn find_parts(div: ElementRef) -> Option<Episode> {
-        let provider = div.select(&s("span.single-heading")).next()?.inner_html();
-        let provider = VideoProvider::find(&provider)?;
-        let p = ElementRef::wrap(div.next_sibling()?)?;
-        let links = p
-            .select(&s("a"))
-            .map(|a| (a.inner_html(), a.value().attr("href")))
-            .filter_map(|(title, opt_link)| {
-                opt_link.map(|link| (fix_title(title), link.to_owned()))
-            })
-            .collect::<Vec<_>>();
-        Some(Episode { provider, links })
-    }
+pub async fn get_episode_parts(
+    state: &AppState,
+    tv_channel: &str,
+    tv_show: &str,
+    episode: &str,
+) -> Option<Vec<Episode>> {
+    let (_, episode) = get_episode(state, tv_channel, tv_show, episode).await?;
+    Some(episode.parts)
+}
 
-    fn find_episode_video_links(html: &str) -> (String, Vec<Episode>) {
-        let doc = Html::parse_document(html);
-        let title = doc
-            .select(&s(".post-single-content header h1.title"))
-            .next()
-            .map(|t| t.inner_html())
-            .unwrap_or_else(|| String::from("NA"));
-        let title = fix_title(title);
-        let mut parts = doc
-            .select(&s(".thecontent div.buttons.btn_green"))
-            .filter_map(find_parts)
-            .collect::<Vec<_>>();
-        parts.sort_by_key(|e| e.provider.priority());
-        (title, parts)
-    }
-    let response = http_client()
-        .get(eps_url)
-        .header(header::REFERER, referer)
-        .send()
+/// Finds a cached episode, along with its tv show.
+pub async fn get_episode(
+    state: &AppState,
+    tv_channel: &str,
+    tv_show: &str,
+    episode: &str,
+) -> Option<(TvShow, TvEpisode)> {
+    let soap = state.tv_channels.get_tv_show(tv_channel, tv_show).await?;
+    let episodes = state
+        .tv_shows
+        .get_tv_show(&cache_key(&soap))
         .await?
-        .text()
-        .await?;
-    Ok(find_episode_video_links(&response))
+        .episodes;
+    // The id is looked up first, the title is matched for the clients which predate the ids.
+    let idx = episodes
+        .iter()
+        .position(|e| e.id == episode)
+        .or_else(|| episodes.iter().position(|e| e.title == episode))?;
+    Some((soap, episodes.into_iter().nth(idx)?))
 }
 
-pub async fn get_episode_parts(
+/// The cached episodes of a tv show, newest first.
+pub async fn cached_episodes(
+    state: &AppState,
     tv_channel: &str,
     tv_show: &str,
-    title: &str,
-) -> Option<Vec<Episode>> {
-    let soap = get_tv_show(tv_channel, tv_show).await?;
-    let state = state::STATE.get()?;
-    let episodes = state
-        .get_tv_show(&format!("{}:{}", soap.title, soap.url))
-        .await?;
-    let eps = episodes
-        .episodes
-        .into_iter()
-        .find(|(t, _)| t == title)
-        .map(|(_, eps)| eps)?;
-    Some(eps)
+) -> Option<Vec<TvEpisode>> {
+    let soap = state.tv_channels.get_tv_show(tv_channel, tv_show).await?;
+    Some(cached_tv_show(state, &soap).await?.episodes)
+}
+
+/// Whatever has been loaded of a tv show, without going to the source site.
+pub async fn cached_tv_show(state: &AppState, soap: &TvShow) -> Option<TvShowEpisodes> {
+    state.tv_shows.get_tv_show(&cache_key(soap)).await
 }
 
 impl VideoProvider {
@@ -311,109 +511,323 @@ impl VideoProvider {
 
 mod state {
     use std::collections::HashMap;
-    use std::path::PathBuf;
-    use std::process;
-    use std::time::SystemTime;
+    use std::time::Duration;
 
-    use once_cell::sync::OnceCell;
-    use serde::{Deserialize, Serialize};
-    use tokio::fs;
     use tokio::sync::RwLock;
     use tracing::*;
 
-    use crate::models::TvShowEpisodes;
-    use crate::utils::{cache_folder, expiry_time, TV_SHOWS_FILE};
-
-    pub(super) static STATE: OnceCell<TvShowsStateWrapper> = OnceCell::new();
+    use crate::models::{OpenedShow, TvShowEpisodes};
+    use crate::store::{Store, OPENED_SHOWS, TV_SHOWS};
 
-    pub(super) struct TvShowsStateWrapper(RwLock<TvShowsState>);
+    /// Every tv show keeps its own freshness, see `TvShowEpisodes::refreshed_at`.
+    pub struct TvShowsStateWrapper {
+        state: RwLock<TvShowsState>,
+        store: Store,
+    }
 
-    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[derive(Debug, Clone)]
     struct TvShowsState {
         map: HashMap<String, TvShowEpisodes>,
-        expires_at: SystemTime,
+        opened: HashMap<String, OpenedShow>,
     }
 
     impl TvShowsStateWrapper {
+        pub async fn load(store: Store) -> Self {
+            let map = store
+                .entries::<TvShowEpisodes>(TV_SHOWS)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Loading of previously saved tv shows failed: {e:?}");
+                    Vec::new()
+                })
+                .into_iter()
+                .collect::<HashMap<_, _>>();
+            let opened = store
+                .entries::<OpenedShow>(OPENED_SHOWS)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Loading of the opened tv shows failed: {e:?}");
+                    Vec::new()
+                })
+                .into_iter()
+                .collect::<HashMap<_, _>>();
+            debug!("Loaded {} tv shows from the store", map.len());
+            TvShowsStateWrapper {
+                state: RwLock::new(TvShowsState { map, opened }),
+                store,
+            }
+        }
+
         pub async fn get_tv_show(&self, key: &str) -> Option<TvShowEpisodes> {
-            let rstate = self.0.read().await;
-            if rstate.expires_at < SystemTime::now() {
-                drop(rstate);
-                warn!("TvShows have already expired, clearing it");
-
-                let mut wstate = self.0.write().await;
-                wstate.map.clear();
-                wstate.expires_at = expiry_time();
-                drop(wstate);
-
-                self.save_state().await;
-                None
-            } else {
-                rstate.map.get(key).cloned()
+            self.state.read().await.map.get(key).cloned()
+        }
+
+        /// Rewrites the keys of the cached tv shows, as they contain the url of the tv show.
+        pub async fn rebase_keys(&self, rebase: impl Fn(&str) -> String) {
+            let mut wstate = self.state.write().await;
+            wstate.map = wstate
+                .map
+                .drain()
+                .map(|(key, tv_show)| (rebase(&key), tv_show))
+                .collect();
+            wstate.opened = wstate
+                .opened
+                .drain()
+                .map(|(key, mut opened)| {
+                    opened.tv_show.url = rebase(&opened.tv_show.url);
+                    opened.tv_show.icon = rebase(&opened.tv_show.icon);
+                    (rebase(&key), opened)
+                })
+                .collect();
+            let entries = wstate.map.clone();
+            let opened = wstate.opened.clone();
+            drop(wstate);
+            self.save(self.store.replace_all(TV_SHOWS, entries).await);
+            self.save(self.store.replace_all(OPENED_SHOWS, opened).await);
+        }
+
+        pub async fn mark_opened(&self, key: &str, opened: OpenedShow) {
+            self.save(self.store.put(OPENED_SHOWS, key, &opened).await);
+            self.state
+                .write()
+                .await
+                .opened
+                .insert(key.to_owned(), opened);
+        }
+
+        /// The tv shows opened within the `window`, the ones opened before it are forgotten.
+        pub async fn recently_opened(&self, window: Duration) -> Vec<OpenedShow> {
+            let is_recent = |opened: &OpenedShow| {
+                opened
+                    .opened_at
+                    .elapsed()
+                    .map_or(true, |elapsed| elapsed <= window)
+            };
+            let mut wstate = self.state.write().await;
+            let forgotten = wstate
+                .opened
+                .iter()
+                .filter(|(_, opened)| !is_recent(opened))
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            for key in &forgotten {
+                wstate.opened.remove(key);
+            }
+            let mut recent = wstate.opened.values().cloned().collect::<Vec<_>>();
+            drop(wstate);
+            for key in forgotten {
+                self.save(self.store.remove(OPENED_SHOWS, &key).await.map(|_| ()));
             }
+            recent.sort_by_key(|opened| std::cmp::Reverse(opened.opened_at));
+            recent
+        }
+
+        pub async fn remove_tv_show(&self, key: &str) -> bool {
+            let removed = self.state.write().await.map.remove(key).is_some();
+            if removed {
+                self.save(self.store.remove(TV_SHOWS, key).await.map(|_| ()));
+            }
+            removed
+        }
+
+        pub async fn len(&self) -> usize {
+            self.state.read().await.map.len()
         }
 
         pub async fn put_tv_show(&self, key: String, tv_show: TvShowEpisodes) {
-            self.0.write().await.map.insert(key, tv_show);
-            self.save_state().await;
+            self.save(self.store.put(TV_SHOWS, &key, &tv_show).await);
+            self.state.write().await.map.insert(key, tv_show);
         }
 
-        async fn save_state(&self) {
-            async fn _save_state(path: PathBuf, state: &TvShowsState) -> anyhow::Result<()> {
-                let state = serde_json::to_string_pretty(state)?;
-                fs::write(path, state).await?;
-                Ok(())
+        /// A failed write is only logged, the state in memory is still served.
+        fn save(&self, result: anyhow::Result<()>) {
+            if let Err(e) = result {
+                error!("Failed to save the tv shows: {e:?}");
             }
+        }
+    }
+}
 
-            debug!("Saving state to file system");
-            let path = PathBuf::from(cache_folder()).join(TV_SHOWS_FILE);
-            _save_state(path, &*self.0.read().await)
-                .await
-                .map_err(|e| {
-                    error!("Failed to save state to file: {e:?}");
-                    process::exit(-1)
-                })
-                .ok();
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use futures::future::BoxFuture;
+    use futures::FutureExt;
+
+    use std::time::{Duration, SystemTime};
+
+    use crate::config::Config;
+    use crate::models::{Episode, OpenedShow, TvShow, TvShowEpisodes, VideoProvider};
+    use crate::sources::{ContentSource, EpisodePage};
+    use crate::store::Store;
+    use crate::utils::TestDir;
+
+    use super::{load_next_page, refresh, TvShowsStateWrapper};
+
+    const URL: &str = "https://example.com/show/";
+
+    /// Lists the episodes newest first, two on every page.
+    struct FakeSource {
+        episodes: Mutex<Vec<String>>,
+    }
+
+    impl FakeSource {
+        fn publish(&self, episode: &str) {
+            self.episodes.lock().unwrap().insert(0, episode.to_owned());
         }
     }
 
-    pub async fn init() {
-        let path = PathBuf::from(cache_folder());
-        if !path.exists() {
-            fs::create_dir_all(&path).await.ok();
+    impl ContentSource for FakeSource {
+        fn name(&self) -> &str {
+            "fake"
         }
-        let path = path.join(TV_SHOWS_FILE);
-        let state = if path.exists() {
-            info!("Loading TvShows state from {path:?}");
-            match fs::read_to_string(&path).await.and_then(|s| {
-                serde_json::from_str(&s).map_err(|_| std::io::ErrorKind::InvalidData.into())
-            }) {
-                Ok(state) => {
-                    debug!("Successfully loaded state file");
-                    state
-                }
-                Err(e) => {
-                    warn!("Loading of previously saved state failed: {e:?}");
-                    fs::remove_file(path)
-                        .await
-                        .map_err(|e| {
-                            error!("Unable to remove the state file: {e:?}");
-                            process::exit(-1)
-                        })
-                        .ok();
-                    TvShowsState {
-                        map: HashMap::new(),
-                        expires_at: expiry_time(),
-                    }
-                }
-            }
-        } else {
-            info!("State file doesn't exist");
-            TvShowsState {
-                map: HashMap::new(),
-                expires_at: expiry_time(),
+
+        fn list_channels(&self) -> BoxFuture<'_, anyhow::Result<Vec<(String, String)>>> {
+            async { Ok(Vec::new()) }.boxed()
+        }
+
+        fn list_shows<'a>(&'a self, _: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<TvShow>>> {
+            async { Ok(Vec::new()) }.boxed()
+        }
+
+        fn list_episodes<'a>(
+            &'a self,
+            _: &'a str,
+            page: usize,
+        ) -> BoxFuture<'a, anyhow::Result<EpisodePage>> {
+            let episodes = self.episodes.lock().unwrap().clone();
+            let links = episodes
+                .chunks(2)
+                .nth(page - 1)
+                .unwrap_or_default()
+                .iter()
+                .map(|episode| format!("https://example.com/{episode}/"))
+                .collect();
+            let last_page = episodes.len().div_ceil(2);
+            async move {
+                Ok(EpisodePage {
+                    url: URL.to_owned(),
+                    links,
+                    cur_page: page,
+                    last_page,
+                })
             }
+            .boxed()
+        }
+
+        fn list_episode_links<'a>(
+            &'a self,
+            episode_url: &'a str,
+            _: &'a str,
+        ) -> BoxFuture<'a, anyhow::Result<(String, Vec<Episode>)>> {
+            let episode = Episode {
+                provider: VideoProvider::FlashPlayer,
+                links: vec![("Part 1".to_owned(), episode_url.to_owned())],
+            };
+            async move { Ok((episode_url.to_owned(), vec![episode])) }.boxed()
+        }
+
+        fn search_shows<'a>(&'a self, _: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<TvShow>>> {
+            async { Ok(Vec::new()) }.boxed()
+        }
+
+        fn reload(&self) -> BoxFuture<'_, anyhow::Result<String>> {
+            async { Ok(String::new()) }.boxed()
+        }
+    }
+
+    fn ids(tv_show: &TvShowEpisodes) -> Vec<&str> {
+        tv_show.episodes.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_pagination_drift() {
+        let source = FakeSource {
+            episodes: Mutex::new(["e5", "e4", "e3", "e2", "e1"].map(String::from).to_vec()),
+        };
+        let config = Config::default();
+        let mut tv_show = TvShowEpisodes::default();
+
+        assert_eq!(
+            load_next_page(&source, &config, URL, &mut tv_show)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(ids(&tv_show), vec!["e5", "e4"]);
+        assert!(tv_show.has_more());
+
+        // e4 moves to the second page, which tells that something new is on the first one.
+        source.publish("e6");
+        let shift = load_next_page(&source, &config, URL, &mut tv_show)
+            .await
+            .unwrap();
+        assert_eq!(shift, 1);
+        assert_eq!(ids(&tv_show), vec!["e5", "e4", "e3"]);
+        refresh(&source, &config, URL, &mut tv_show).await.unwrap();
+        assert_eq!(ids(&tv_show), vec!["e6", "e5", "e4", "e3"]);
+
+        source.publish("e7");
+        source.publish("e8");
+        refresh(&source, &config, URL, &mut tv_show).await.unwrap();
+        assert_eq!(ids(&tv_show), vec!["e8", "e7", "e6", "e5", "e4", "e3"]);
+        assert!(tv_show.has_more());
+
+        // e2 & e1 are on the last page now, nothing is skipped.
+        assert_eq!(
+            load_next_page(&source, &config, URL, &mut tv_show)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            ids(&tv_show),
+            vec!["e8", "e7", "e6", "e5", "e4", "e3", "e2", "e1"]
+        );
+        assert!(!tv_show.has_more());
+
+        source.publish("e9");
+        refresh(&source, &config, URL, &mut tv_show).await.unwrap();
+        assert_eq!(tv_show.episodes.len(), 9);
+        assert!(!tv_show.has_more());
+    }
+
+    #[tokio::test]
+    async fn test_recently_opened() {
+        let cache_dir = TestDir::new("opened");
+        let store = Store::open(&cache_dir).await.unwrap();
+        let tv_shows = TvShowsStateWrapper::load(store.clone()).await;
+        let opened = |title: &str, age: u64| OpenedShow {
+            channel: "star-plus".into(),
+            tv_show: TvShow {
+                title: title.into(),
+                url: format!("{URL}{title}/"),
+                icon: "icon".into(),
+            },
+            opened_at: SystemTime::now() - Duration::from_secs(age),
+        };
+        tv_shows.mark_opened("old", opened("old", 100)).await;
+        tv_shows.mark_opened("older", opened("older", 200)).await;
+        tv_shows.mark_opened("new", opened("new", 10)).await;
+
+        let titles = |opened: Vec<OpenedShow>| {
+            opened
+                .into_iter()
+                .map(|opened| opened.tv_show.title)
+                .collect::<Vec<_>>()
         };
-        STATE.set(TvShowsStateWrapper(RwLock::new(state))).ok();
+        let window = Duration::from_secs(150);
+        assert_eq!(
+            titles(tv_shows.recently_opened(window).await),
+            vec!["new", "old"]
+        );
+        // The forgotten show isn't loaded again.
+        let tv_shows = TvShowsStateWrapper::load(store).await;
+        let window = Duration::from_secs(1000);
+        assert_eq!(
+            titles(tv_shows.recently_opened(window).await),
+            vec!["new", "old"]
+        );
     }
 }
@@ -1,8 +1,6 @@
-use std::time::{Duration as StdDuration, SystemTime};
+use std::time::SystemTime;
 
-use chrono::{Datelike, Duration, Local, NaiveDate};
-use once_cell::sync::OnceCell;
-use tracing::*;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
 
 pub use title_util::fix_title;
 
@@ -10,22 +8,9 @@ pub const TV_CHANNEL_FILE: &str = "channels.json";
 
 pub const TV_SHOWS_FILE: &str = "tv_shows.json";
 
-pub const EXPIRY: StdDuration = StdDuration::from_secs(2 * 24 * 60 * 60);
+pub const MIRROR_FILE: &str = "mirror.txt";
 
-static CACHE_FOLDER: OnceCell<String> = OnceCell::new();
-
-pub fn set_cache_folder(folder: &str) -> anyhow::Result<()> {
-    std::fs::create_dir_all(folder)?;
-    CACHE_FOLDER
-        .set(folder.to_owned())
-        .map_err(|_| anyhow::anyhow!("Failed to init the cache folder: {folder}"))?;
-    info!("Using cache folder: {folder}");
-    Ok(())
-}
-
-pub fn cache_folder() -> &'static str {
-    CACHE_FOLDER.get().map(|s| s as &str).unwrap_or("cache")
-}
+pub const STATE_DB: &str = "state.redb";
 
 #[allow(deprecated)]
 pub fn expiry_time() -> SystemTime {
@@ -36,11 +21,72 @@ pub fn expiry_time() -> SystemTime {
     SystemTime::now() + diff.to_std().unwrap()
 }
 
+pub fn to_rfc3339(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).to_rfc3339()
+}
+
 pub fn hash(input: impl AsRef<[u8]>) -> String {
     let hash_val = seahash::hash(input.as_ref());
     format!("{hash_val:x}")
 }
 
+/// Lowercase words of `text` joined by `-`, e.g. "Star Plus" => "star-plus".
+///
+/// The words are ASCII only, so a text with anything more than them and single spaces, e.g. `&`,
+/// punctuation or non-Latin letters, gets a short hash of itself as suffix to keep it apart from
+/// the texts with the same words. A text without any ASCII word is only its hash.
+pub fn slugify(text: &str) -> String {
+    let slug = words(text);
+    let plain = text
+        .split(' ')
+        .all(|word| !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric()));
+    if slug.is_empty() {
+        hash(text)
+    } else if plain {
+        slug
+    } else {
+        format!("{slug}-{}", short_hash(text))
+    }
+}
+
+/// Id of a page of the source site, taken from its path so that it survives the mirror switches.
+///
+/// A page right under the root is the slug of its path, e.g. "/anupamaa/" => "anupamaa", the
+/// deeper or irregular ones get a short hash of the whole path as suffix.
+pub fn url_id(url: &str) -> String {
+    let path = url::Url::parse(url)
+        .map(|url| url.path().to_owned())
+        .unwrap_or_else(|_| url.to_owned());
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let path = format!("/{}", segments.join("/"));
+    let slug = segments
+        .last()
+        .map(|segment| words(segment))
+        .unwrap_or_default();
+    if slug.is_empty() {
+        hash(path)
+    } else if segments.len() == 1 && segments[0] == slug {
+        slug
+    } else {
+        format!("{slug}-{}", short_hash(path))
+    }
+}
+
+fn words(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn short_hash(input: impl AsRef<[u8]>) -> String {
+    format!("{:06x}", seahash::hash(input.as_ref()) & 0xff_ffff)
+}
+
 pub fn encode_uri_component(input: impl AsRef<[u8]>) -> String {
     form_urlencoded::byte_serialize(input.as_ref()).collect()
 }
@@ -76,10 +122,47 @@ mod title_util {
     }
 }
 
+/// Folder of a test, unique to the test & the process so that concurrent runs don't share it,
+/// it's deleted once dropped.
+#[cfg(test)]
+pub struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(name: &str) -> TestDir {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir_name = format!("tv_shows_{name}_{}_{count}", std::process::id());
+        let path = std::env::temp_dir().join(dir_name);
+        std::fs::remove_dir_all(&path).ok();
+        std::fs::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::expiry_time;
     use super::fix_title;
+    use super::{slugify, url_id};
 
     #[test]
     fn test_expiry() {
@@ -93,4 +176,40 @@ mod test {
         println!("{}", fix_title("&amp; TV Shows"));
         println!("{}", fix_title("Naagini 10th September 2022&nbsp;"));
     }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(slugify("Star Plus"), "star-plus");
+        assert!(slugify("&TV / Zee?").starts_with("tv-zee-"));
+        assert_eq!(url_id("https://www.yodesitv.info/anupamaa/"), "anupamaa");
+        assert_eq!(
+            url_id("https://www.desitellybox.me/anupamaa/page/2/"),
+            url_id("https://www.desitellybox.me/anupamaa/page/2")
+        );
+        assert_eq!(url_id("https://www.yodesitv.info/"), super::hash("/"));
+    }
+
+    #[test]
+    fn test_unique_ids() {
+        // Non-Latin titles aren't left without an id.
+        let hindi = slugify("अनुपमा");
+        assert!(!hindi.is_empty());
+        assert_ne!(hindi, slugify("नागिन"));
+        assert!(slugify("Anupamaa (हिंदी)").starts_with("anupamaa-"));
+
+        // Titles with the same words.
+        assert_ne!(slugify("Tu Aashiqui"), slugify("Tu Aashiqui!"));
+        assert_ne!(slugify("Khatra Khatra"), slugify("Khatra & Khatra"));
+        assert_ne!(slugify("Star-Plus"), slugify("Star Plus"));
+
+        // Pages with the same last segment.
+        let page = url_id("https://www.desitellybox.me/anupamaa/page/2/");
+        assert!(page.starts_with("2-"));
+        assert_ne!(page, url_id("https://www.desitellybox.me/imlie/page/2/"));
+        assert_ne!(page, url_id("https://www.desitellybox.me/2/"));
+        assert_ne!(
+            url_id("https://www.desitellybox.me/Anupamaa/"),
+            url_id("https://www.desitellybox.me/anupamaa/")
+        );
+    }
 }
@@ -1,46 +1,79 @@
 use futures::future::BoxFuture;
 use futures::FutureExt;
-use once_cell::sync::OnceCell;
 use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{mpsc, oneshot};
 use tokio::time;
 use tracing::{debug, error};
 
-static TASK_SENDER: OnceCell<UnboundedSender<BoxFuture<'static, ()>>> = OnceCell::new();
+use crate::error::ServerError;
+use crate::metrics::record_worker_timeout;
 
-pub async fn init_worker() {
-    let (tx, mut rx) = mpsc::unbounded_channel();
-    TASK_SENDER.set(tx).expect("Failed setting the TASK_SENDER");
+const TASK_TIMEOUT: Duration = Duration::from_secs(30);
 
-    let mut task_count = 1;
-    while let Some(task) = rx.recv().await {
-        debug!("Executing next task: {task_count}");
-        task_count += 1;
-        if let Err(e) = time::timeout(Duration::from_secs(30), task).await {
-            error!("Timeout while executing an async task: {e}");
+#[derive(Clone)]
+pub struct Worker {
+    task_sender: UnboundedSender<BoxFuture<'static, ()>>,
+    queued: Arc<AtomicUsize>,
+    executed: Arc<AtomicUsize>,
+}
+
+impl Worker {
+    /// Spawns the worker loop, which stops once all the `Worker` clones are dropped.
+    pub fn start() -> Worker {
+        let (tx, mut rx) = mpsc::unbounded_channel::<BoxFuture<'static, ()>>();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let executed = Arc::new(AtomicUsize::new(0));
+        let (task_queued, task_executed) = (queued.clone(), executed.clone());
+        tokio::spawn(async move {
+            while let Some(task) = rx.recv().await {
+                task_queued.fetch_sub(1, Ordering::Relaxed);
+                let task_count = task_executed.fetch_add(1, Ordering::Relaxed) + 1;
+                debug!("Executing next task: {task_count}");
+                if let Err(e) = time::timeout(TASK_TIMEOUT, task).await {
+                    error!("Timeout while executing an async task: {e}");
+                    record_worker_timeout();
+                }
+            }
+        });
+        Worker {
+            task_sender: tx,
+            queued,
+            executed,
         }
     }
-}
 
-/// Runs the async task in sequence.
-pub async fn run<T: Send + 'static>(
-    job: impl Future<Output = anyhow::Result<T>> + Send + 'static,
-) -> anyhow::Result<T> {
-    let (tx, rx) = oneshot::channel::<anyhow::Result<T>>();
+    /// Number of tasks waiting for their turn.
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
 
-    let task = async move {
-        tx.send(job.await).ok();
+    /// Number of tasks picked up since the worker started.
+    pub fn tasks_executed(&self) -> usize {
+        self.executed.load(Ordering::Relaxed)
     }
-    .boxed();
-    let _ = TASK_SENDER
-        .get()
-        .ok_or_else(|| anyhow::anyhow!("TASK_SENDER is not yet initialized"))
-        .and_then(|task_sender| {
-            task_sender
-                .send(task)
-                .map_err(|_| anyhow::anyhow!("Couldn't send the task to TASK_SENDER"))
+
+    /// Runs the async task in sequence.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        job: impl Future<Output = anyhow::Result<T>> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let (tx, rx) = oneshot::channel::<anyhow::Result<T>>();
+
+        let task = async move {
+            tx.send(job.await).ok();
+        }
+        .boxed();
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.task_sender.send(task).map_err(|_| {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            ServerError::NotInitialized("Couldn't send the task to the worker".into())
         })?;
-    rx.await?
+        // The sender is only dropped without a result when the task was timed out.
+        rx.await
+            .map_err(|_| ServerError::Timeout(format!("Task didn't finish in {TASK_TIMEOUT:?}")))?
+    }
 }